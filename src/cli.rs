@@ -1,9 +1,15 @@
 mod cli_model;
 mod config;
 mod init_log;
+mod input_format;
+mod iupac;
 mod log_level;
+mod pairing;
 
-pub use config::Config;
+pub use config::{Amplicon, Config, InputMode, is_stdin};
+pub use input_format::InputFormat;
+pub use iupac::{iupac_compatible, iupac_complement, iupac_mask};
+pub use pairing::Pairing;
 
 pub fn handle_cli() -> anyhow::Result<Config> {
     let c = cli_model::cli_model();
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use clap::{Arg, ArgAction, Command, command, value_parser};
-use super::log_level::LogLevel;
+use super::{input_format::InputFormat, log_level::LogLevel, pairing::Pairing};
 
 pub(super) fn cli_model() -> Command {
     command!()
@@ -47,6 +47,41 @@ pub(super) fn cli_model() -> Command {
                 .value_name("INT")
                 .help("Set number of read threads [default: MIN (physical cores, no. input files)"),
         )
+        .arg(
+            Arg::new("no_read_ahead")
+                .action(ArgAction::SetTrue)
+                .long("no-read-ahead")
+                .help("Do not use separate threads to read and decompress each input file (except for streams)"),
+        )
+        .arg(
+            Arg::new("mmap")
+                .action(ArgAction::SetTrue)
+                .long("mmap")
+                .help("Use memory mapping to read uncompressed FASTQ input files"),
+        )
+        .arg(
+            Arg::new("buffers")
+                .long("buffers")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Set number of input buffers [default: 4 * MAX (threads, readers)]"),
+        )
+        .arg(
+            Arg::new("buffer_size")
+                .long("buffer-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("KiB")
+                .default_value("1024")
+                .help("Initial size of input buffers in KiB"),
+        )
+        .arg(
+            Arg::new("max_buffer_size")
+                .long("max-buffer-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("KiB")
+                .default_value("262144")
+                .help("Maximum size of input buffers in KiB (buffers grow to fit long records)"),
+        )
         .arg(
             Arg::new("min_qual")
                 .long("min-qual")
@@ -56,6 +91,14 @@ pub(super) fn cli_model() -> Command {
                 .default_value("0")
                 .help("Minimum base quality to consider"),
         )
+        .arg(
+            Arg::new("qual_offset")
+                .long("qual-offset")
+                .value_parser(["auto", "33", "64"])
+                .value_name("OFFSET")
+                .default_value("auto")
+                .help("Offset of base quality values (auto: detect from input)"),
+        )
         .arg(
             Arg::new("max_overlap_divergence")
                 .long("max-overlap-divergence")
@@ -72,6 +115,92 @@ pub(super) fn cli_model() -> Command {
                 .default_value("10")
                 .help("Maximum divergence allowed in length of consensus sequence"),
         )
+        .arg(
+            Arg::new("merge_free_ends")
+                .long("merge-free-ends")
+                .value_parser(value_parser!(u32))
+                .value_name("INT")
+                .default_value("15")
+                .help("Maximum unaligned sequence allowed at the end of read 1 and the start of (reverse complemented) read 2 when merging"),
+        )
+        .arg(
+            Arg::new("merge_penalties")
+                .long("merge-penalties")
+                .value_name("M,X,O,E")
+                .default_value("0,4,6,2")
+                .help("Penalties (match, mismatch, gap open, gap extend) for alignment of read 1 and read 2 when merging"),
+        )
+        .arg(
+            Arg::new("trim_adapters")
+                .action(ArgAction::SetTrue)
+                .long("trim-adapters")
+                .help("Trim adapter read through before merging (detected from the overlap of read pairs unless --adapter is given)"),
+        )
+        .arg(
+            Arg::new("adapter")
+                .long("adapter")
+                .value_name("SEQ")
+                .help("Adapter sequence at the 3' end of read 1 (and of read 2 unless --adapter2 is given); implies --trim-adapters"),
+        )
+        .arg(
+            Arg::new("adapter2")
+                .long("adapter2")
+                .value_name("SEQ")
+                .requires("adapter")
+                .help("Adapter sequence at the 3' end of read 2"),
+        )
+        .arg(
+            Arg::new("forward_primer")
+                .long("forward-primer")
+                .value_name("SEQ")
+                .requires("reverse_primer")
+                .help("Forward primer sequence (IUPAC codes allowed; single reference record only): reads without the primers are rejected, and primer positions are not counted"),
+        )
+        .arg(
+            Arg::new("reverse_primer")
+                .long("reverse-primer")
+                .value_name("SEQ")
+                .requires("forward_primer")
+                .help("Reverse primer sequence (5' to 3' on the reverse strand)"),
+        )
+        .arg(
+            Arg::new("join_unmerged")
+                .action(ArgAction::SetTrue)
+                .long("join-unmerged")
+                .help("Align read pairs that can not be merged to either end of the reference and join them with a masked gap (each read must be within the maximum overlap divergence of the reference)"),
+        )
+        .arg(
+            Arg::new("ref_penalties")
+                .long("ref-penalties")
+                .value_name("M,X,O,E")
+                .default_value("0,4,6,2")
+                .conflicts_with("long_reads")
+                .help("Penalties (match, mismatch, gap open, gap extend) for alignment of merged reads to the reference"),
+        )
+        .arg(
+            Arg::new("long_reads")
+                .action(ArgAction::SetTrue)
+                .long("long-reads")
+                .conflicts_with("interleaved")
+                .help("Input reads are full length single molecule reads (e.g., ONT, PacBio) in either orientation (implies --single-end)"),
+        )
+        .arg(
+            Arg::new("long_read_penalties")
+                .long("long-read-penalties")
+                .value_name("M,X,O,E")
+                .default_value("0,3,2,1")
+                .requires("long_reads")
+                .help("Penalties (match, mismatch, gap open, gap extend) for alignment of long reads to the reference"),
+        )
+        .arg(
+            Arg::new("long_read_length_window")
+                .long("long-read-length-window")
+                .value_parser(value_parser!(f64))
+                .value_name("FRAC")
+                .default_value("0.1")
+                .requires("long_reads")
+                .help("Maximum divergence allowed in length of long reads as a fraction of amplicon length"),
+        )
         .arg(
             Arg::new("ignore_multibase_deletions")
                 .action(ArgAction::SetTrue)
@@ -114,7 +243,7 @@ pub(super) fn cli_model() -> Command {
                 .value_parser(value_parser!(PathBuf))
                 .value_name("FILE")
                 .required(true)
-                .help("Reference sequence FASTA"),
+                .help("Reference FASTA (one record per amplicon)"),
         )
         .arg(
             Arg::new("output_prefix")
@@ -125,13 +254,87 @@ pub(super) fn cli_model() -> Command {
                 .default_value("ampl_seq")
                 .help("Prefix for output file"),
         )
+        .arg(
+            Arg::new("interleaved")
+                .action(ArgAction::SetTrue)
+                .long("interleaved")
+                .short('I')
+                .help("Input files contain interleaved read pairs"),
+        )
+        .arg(
+            Arg::new("pairing")
+                .long("pairing")
+                .value_parser(value_parser!(Pairing))
+                .value_name("RULE")
+                .ignore_case(true)
+                .default_value("strict")
+                .help("Rule for checking that read 1 and read 2 IDs match"),
+        )
+        .arg(
+            Arg::new("allow_unequal_records")
+                .action(ArgAction::SetTrue)
+                .long("allow-unequal-records")
+                .help("Warn rather than fail if paired input files have different numbers of records"),
+        )
+        .arg(
+            Arg::new("max_bad_records")
+                .long("max-bad-records")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .default_value("0")
+                .help("Skip up to INT invalid records (or record pairs) before failing"),
+        )
+        .arg(
+            Arg::new("single_end")
+                .action(ArgAction::SetTrue)
+                .long("single-end")
+                .short('S')
+                .conflicts_with("interleaved")
+                .help("Input reads are single-end or already merged (skip overlap step)"),
+        )
+        .arg(
+            Arg::new("input_format")
+                .short('F')
+                .long("input-format")
+                .value_name("FORMAT")
+                .value_parser(value_parser!(InputFormat))
+                .ignore_case(true)
+                .default_value("fastq")
+                .help("Format of input files (SAM/BAM should be unaligned; pairs are taken from a single file; FASTA implies --single-end)"),
+        )
+        .arg(
+            Arg::new("fasta_qual")
+                .long("fasta-qual")
+                .value_parser(value_parser!(u8).range(0..=93))
+                .value_name("QUAL")
+                .default_value("40")
+                .help("Base quality assumed for FASTA input"),
+        )
+        .arg(
+            Arg::new("sample_sheet")
+                .short('s')
+                .long("sample-sheet")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("FILE")
+                .help("Sample sheet (TSV/CSV with sample, [lane,] R1, R2 or sample, [lane,] file if interleaved or single-end)"),
+        )
+        .arg(
+            Arg::new("input_dir")
+                .short('i')
+                .long("input-dir")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("DIR")
+                .conflicts_with("sample_sheet")
+                .help("Directory with paired FASTQ files (Illumina naming); lanes are merged per sample; index and Undetermined files are skipped"),
+        )
         .arg(
             Arg::new("input")
                 .value_parser(value_parser!(PathBuf))
                 .value_name("INPUT")
                 .action(ArgAction::Append)
-                .num_args(2..)
-                .required(true)
-                .help("Input FASTQ file(s)"),
+                .num_args(1..)
+                .required_unless_present_any(["sample_sheet", "input_dir"])
+                .conflicts_with_all(["sample_sheet", "input_dir"])
+                .help("Input FASTQ file(s) ('-' for stdin with interleaved or single-end input)"),
         )
 }
\ No newline at end of file
@@ -1,15 +1,30 @@
+mod amplicon;
+mod discover;
 mod getters;
+mod input;
 mod mk_config;
+mod sample_sheet;
 
-use std::path::PathBuf;
+pub use amplicon::Amplicon;
+pub use input::{Input, InputMode, is_stdin};
+
+use super::{input_format::InputFormat, pairing::Pairing};
 
 pub struct Config {
     min_qual: u8,
+    qual_offset: Option<u8>,
     output_prefix: String,
     threads: usize,
     readers: usize,
-    reference: Vec<u8>,
-    input_files: Vec<PathBuf>,
+    buffers: usize,
+    buffer_size: usize,
+    max_buffer_size: usize,
+    amplicons: Vec<Amplicon>,
+    samples: Vec<String>,
+    input_format: InputFormat,
+    input_mode: InputMode,
+    inputs: Vec<Input>,
+    pairing: Pairing,
     max_length_divergence: u32,
     max_overlap_divergence: u32,
     ignore_multibase_deletions: bool,
@@ -17,4 +32,18 @@ pub struct Config {
     ignore_multiple_mutations: bool,
     ignore_multiple_modifications: bool,
     view_file: bool,
+    allow_unequal_records: bool,
+    max_bad_records: u64,
+    read_ahead: bool,
+    mmap: bool,
+    fasta_qual: u8,
+    long_reads: bool,
+    long_read_length_window: f64,
+    merge_free_ends: u32,
+    merge_penalties: [i32; 4],
+    ref_penalties: [i32; 4],
+    join_unmerged: bool,
+    trim_adapters: bool,
+    adapters: Option<[Vec<u8>; 2]>,
+    primers: Option<[Vec<u8>; 2]>,
 }
@@ -0,0 +1,37 @@
+use std::ops::Range;
+
+/// A single reference record (amplicon)
+pub struct Amplicon {
+    name: String,
+    seq: Vec<u8>,
+    target: Range<usize>,
+}
+
+impl Amplicon {
+    pub(super) fn new(name: String, seq: Vec<u8>) -> Self {
+        let target = 0..seq.len();
+        Self { name, seq, target }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn seq(&self) -> &[u8] {
+        &self.seq
+    }
+
+    /// Part of the reference between the primers (the whole reference if it does not
+    /// include the primers)
+    pub fn target(&self) -> &[u8] {
+        &self.seq[self.target.clone()]
+    }
+
+    pub fn target_range(&self) -> Range<usize> {
+        self.target.clone()
+    }
+
+    pub(super) fn set_target(&mut self, target: Range<usize>) {
+        self.target = target
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use super::Input;
+
+/// Files for one sample, one pair per lane
+type SampleFiles = (String, Vec<[PathBuf; 2]>);
+
+/// Read 1 and read 2 files for a sample indexed by lane and segment
+type LaneFiles = BTreeMap<(String, String), [Option<PathBuf>; 2]>;
+
+/// Scan directory for paired FASTQ files using Illumina naming conventions.
+///
+/// Files are paired by sample and lane, and all lanes for a sample are merged.  Index read
+/// files (`_I<1|2>`) and files with reads not assigned to a sample (`Undetermined`) are skipped.
+/// Returns the list of sample names and the list of inputs
+pub(super) fn read_input_dir(dir: &Path) -> anyhow::Result<(Vec<String>, Vec<Input>)> {
+    info!("Scanning input directory {}", dir.display());
+
+    let mut files = Vec::new();
+    for e in dir
+        .read_dir()
+        .with_context(|| format!("Could not read input directory {}", dir.display()))?
+    {
+        let p = e
+            .with_context(|| format!("Error reading input directory {}", dir.display()))?
+            .path();
+        if p.is_file()
+            && let Some(name) = p.file_name().and_then(|s| s.to_str())
+            && strip_fastq_ext(name).is_some()
+            && !skip_file(name)
+        {
+            files.push(p)
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No sample FASTQ files found in {}", dir.display()));
+    }
+
+    let v = pair_files(files)?.ok_or_else(|| {
+        anyhow!(
+            "Could not parse FASTQ file names in {} (expecting Illumina naming)",
+            dir.display()
+        )
+    })?;
+
+    let mut samples = Vec::with_capacity(v.len());
+    let mut inputs = Vec::new();
+    for (ix, (name, pairs)) in v.into_iter().enumerate() {
+        samples.push(name);
+        for f in pairs {
+            inputs.push(Input::new(ix, f.into()))
+        }
+    }
+
+    info!(
+        "Found {} sample(s) with {} input file pair(s)",
+        samples.len(),
+        inputs.len()
+    );
+    Ok((samples, inputs))
+}
+
+/// Check for index read files and files with reads not assigned to a sample
+fn skip_file(name: &str) -> bool {
+    match parse_name(name) {
+        Some(f) if f.index => {
+            info!("Skipping index read file {name}");
+            true
+        }
+        Some(f) if f.sample == "Undetermined" => {
+            info!("Skipping undetermined read file {name}");
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Pair FASTQ files using the Illumina naming convention
+/// (`<sample>[_S<n>][_L<lane>]_R<1|2>[_<segment>].fastq[.gz]`) or the `<sample>_<1|2>.fastq[.gz]`
+/// variant.
+///
+/// Returns None if any of the files does not follow one of these conventions, or an error if
+/// the files are recognized but can not be paired up (or are index read files)
+pub(super) fn pair_files(files: Vec<PathBuf>) -> anyhow::Result<Option<Vec<SampleFiles>>> {
+    let mut hash: BTreeMap<String, LaneFiles> = BTreeMap::new();
+
+    for p in files {
+        let Some(name) = p.file_name().and_then(|s| s.to_str()).and_then(parse_name) else {
+            return Ok(None);
+        };
+        if name.index {
+            return Err(anyhow!("Input file {} is an index read file", p.display()));
+        }
+        let read = name.read;
+        let e = hash
+            .entry(name.sample.to_owned())
+            .or_default()
+            .entry((name.lane.to_owned(), name.segment.to_owned()))
+            .or_default();
+        match &e[read] {
+            Some(p1) => {
+                return Err(anyhow!(
+                    "Input files {} and {} have the same sample, lane and read",
+                    p1.display(),
+                    p.display()
+                ));
+            }
+            None => e[read] = Some(p),
+        }
+    }
+
+    let mut unpaired = Vec::new();
+    let mut samples = Vec::with_capacity(hash.len());
+    for (sample, lanes) in hash {
+        let mut v = Vec::with_capacity(lanes.len());
+        for (_, files) in lanes {
+            match files {
+                [Some(f1), Some(f2)] => v.push([f1, f2]),
+                [Some(f), None] | [None, Some(f)] => unpaired.push(f.display().to_string()),
+                [None, None] => {}
+            }
+        }
+        samples.push((sample, v))
+    }
+
+    if unpaired.is_empty() {
+        Ok(Some(samples))
+    } else {
+        Err(anyhow!("Unpaired input file(s): {}", unpaired.join(", ")))
+    }
+}
+
+/// Components of a FASTQ file name
+struct FileName<'a> {
+    sample: &'a str,
+    lane: &'a str,
+    segment: &'a str,
+    read: usize,
+    /// Index read (I1 or I2)
+    index: bool,
+}
+
+fn parse_name(name: &str) -> Option<FileName<'_>> {
+    let stem = strip_fastq_ext(name)?;
+    let is_read = |r: &str| matches!(r, "1" | "2");
+    // Read tag (_R1) or index read tag (_I1)
+    let read_tag = |s| {
+        ["_R", "_I"].into_iter().find_map(|tag| {
+            split_num(s, tag)
+                .filter(|(_, rd)| is_read(rd))
+                .map(|(r, rd)| (r, rd, tag == "_I"))
+        })
+    };
+
+    // Read tag: _R1_001, _R1 or _1
+    let (rest, read, index, segment) = if let Some((r, seg)) = split_num(stem, "_")
+        && let Some((r, rd, ix)) = read_tag(r)
+    {
+        (r, rd, ix, seg)
+    } else if let Some((r, rd, ix)) = read_tag(stem) {
+        (r, rd, ix, "")
+    } else if let Some((r, rd)) = split_num(stem, "_")
+        && is_read(rd)
+    {
+        (r, rd, false, "")
+    } else {
+        return None;
+    };
+
+    let (rest, lane) = split_num(rest, "_L").unwrap_or((rest, ""));
+    let (sample, _) = split_num(rest, "_S").unwrap_or((rest, ""));
+    if sample.is_empty() {
+        None
+    } else {
+        Some(FileName {
+            sample,
+            lane,
+            segment,
+            read: if read == "1" { 0 } else { 1 },
+            index,
+        })
+    }
+}
+
+/// Split `s` at the last occurrence of `tag` if `tag` is followed only by digits.
+/// Returns the part before `tag` and the digits
+fn split_num<'a>(s: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let i = s.rfind(tag)?;
+    let n = &s[i + tag.len()..];
+    if !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()) {
+        Some((&s[..i], n))
+    } else {
+        None
+    }
+}
+
+/// Remove (optional) compression suffix and FASTQ suffix.  Returns None if the name
+/// does not have a FASTQ suffix
+fn strip_fastq_ext(name: &str) -> Option<&str> {
+    let s = [".gz", ".bz2", ".xz", ".zst"]
+        .iter()
+        .find_map(|x| name.strip_suffix(x))
+        .unwrap_or(name);
+    [".fastq", ".fq"].iter().find_map(|x| s.strip_suffix(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> Option<(&str, &str, &str, usize, bool)> {
+        parse_name(name).map(|f| (f.sample, f.lane, f.segment, f.read, f.index))
+    }
+
+    #[test]
+    fn split_num_at_last_tag() {
+        assert_eq!(split_num("a_S1_L001", "_L"), Some(("a_S1", "001")));
+        assert_eq!(split_num("a_L1_L002", "_L"), Some(("a_L1", "002")));
+        assert_eq!(split_num("a_L001x", "_L"), None);
+        assert_eq!(split_num("a_L", "_L"), None);
+        assert_eq!(split_num("a", "_L"), None);
+    }
+
+    #[test]
+    fn parse_illumina_names() {
+        assert_eq!(
+            parse("sample1_S3_L001_R1_001.fastq.gz"),
+            Some(("sample1", "001", "001", 0, false))
+        );
+        assert_eq!(
+            parse("sample1_S3_L002_R2_001.fq"),
+            Some(("sample1", "002", "001", 1, false))
+        );
+        assert_eq!(parse("s_x_R2.fastq.bz2"), Some(("s_x", "", "", 1, false)));
+        assert_eq!(parse("s_1.fastq"), Some(("s", "", "", 0, false)));
+        assert_eq!(
+            parse("sample1_S3_L001_I1_001.fastq.gz"),
+            Some(("sample1", "001", "001", 0, true))
+        );
+        assert_eq!(parse("s_I2.fq.gz"), Some(("s", "", "", 1, true)));
+        assert_eq!(
+            parse("Undetermined_S0_L001_R1_001.fastq.gz"),
+            Some(("Undetermined", "001", "001", 0, false))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_names() {
+        assert!(parse("sample_R3_001.fastq").is_none());
+        assert!(parse("sample.fastq").is_none());
+        assert!(parse("_R1.fastq").is_none());
+        assert!(parse("sample_R1_001.txt").is_none());
+    }
+
+    #[test]
+    fn skip_index_and_undetermined() {
+        assert!(skip_file("a_S1_L001_I1_001.fastq.gz"));
+        assert!(skip_file("Undetermined_S0_L001_R1_001.fastq.gz"));
+        assert!(!skip_file("a_S1_L001_R1_001.fastq.gz"));
+        assert!(!skip_file("a.fastq"));
+    }
+
+    #[test]
+    fn pair_explicit_files() {
+        let files = [
+            "a_S1_L002_R2_001.fastq.gz",
+            "a_S1_L001_R1_001.fastq.gz",
+            "a_S1_L001_R2_001.fastq.gz",
+            "a_S1_L002_R1_001.fastq.gz",
+            "Undetermined_S0_L001_R1_001.fastq.gz",
+            "Undetermined_S0_L001_R2_001.fastq.gz",
+        ];
+        let v = pair_files(files.iter().map(PathBuf::from).collect())
+            .unwrap()
+            .unwrap();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].0, "Undetermined");
+        assert_eq!(v[1].0, "a");
+        assert_eq!(
+            v[1].1,
+            vec![
+                [files[1], files[2]].map(PathBuf::from),
+                [files[3], files[0]].map(PathBuf::from)
+            ]
+        );
+
+        let files = ["a_S1_R1_001.fastq.gz", "a_S1_I1_001.fastq.gz"];
+        assert!(pair_files(files.iter().map(PathBuf::from).collect()).is_err());
+    }
+}
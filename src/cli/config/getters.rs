@@ -1,22 +1,62 @@
-use std::path::PathBuf;
-
-use super::Config;
+use super::{Amplicon, Config, Input, InputFormat, InputMode, Pairing};
 
 impl Config {
     pub fn min_qual(&self) -> u8 {
         self.min_qual
     }
+
+    /// Offset of base quality values (None if detected from the input)
+    pub fn qual_offset(&self) -> Option<u8> {
+        self.qual_offset
+    }
     
     pub fn output_prefix(&self) -> &str {
         self.output_prefix.as_ref()
     }   
    
-   pub fn reference(&self) -> &[u8] {
-       self.reference.as_ref()
-   } 
-   
-   pub fn input_files(&self) -> &[PathBuf] {
-       self.input_files.as_ref()
+   pub fn amplicons(&self) -> &[Amplicon] {
+       self.amplicons.as_ref()
+   }
+
+   /// Number of samples (1 if no sample sheet was given)
+   pub fn n_samples(&self) -> usize {
+       self.samples.len().max(1)
+   }
+
+   /// Output prefix for a sample.  The sample name is only added if a sample sheet was given
+   pub fn sample_prefix(&self, sample: usize) -> String {
+       match self.sample_name(sample) {
+           Some(s) => format!("{}_{s}", self.output_prefix()),
+           None => self.output_prefix().to_owned(),
+       }
+   }
+
+   /// Output prefix for the statistics of an amplicon within a sample.  The record name is
+   /// only added if the reference has more than one record
+   pub fn amplicon_prefix(&self, sample: usize, amplicon: usize) -> String {
+       let prefix = self.sample_prefix(sample);
+       if self.amplicons.len() > 1 {
+           format!("{prefix}_{}", self.amplicons[amplicon].name())
+       } else {
+           prefix
+       }
+   }
+
+   pub fn inputs(&self) -> &[Input] {
+       self.inputs.as_ref()
+   }
+
+   pub fn input_mode(&self) -> InputMode {
+       self.input_mode
+   }
+
+   pub fn input_format(&self) -> InputFormat {
+       self.input_format
+   }
+
+   /// Name of sample; None if no sample sheet was given
+   pub fn sample_name(&self, ix: usize) -> Option<&str> {
+       self.samples.get(ix).map(|s| s.as_str())
    }
    
    pub fn threads(&self) -> usize {
@@ -46,6 +86,91 @@ impl Config {
    pub fn view_file(&self) -> bool {
        self.view_file
    }
+
+   pub fn buffers(&self) -> usize {
+       self.buffers
+   }
+
+   pub fn buffer_size(&self) -> usize {
+       self.buffer_size
+   }
+
+   pub fn max_buffer_size(&self) -> usize {
+       self.max_buffer_size
+   }
+
+   pub fn pairing(&self) -> Pairing {
+       self.pairing
+   }
+
+   pub fn allow_unequal_records(&self) -> bool {
+       self.allow_unequal_records
+   }
+
+   /// Read (and decompress) each input file in a separate thread
+   pub fn read_ahead(&self) -> bool {
+       self.read_ahead
+   }
+
+   /// Memory map uncompressed input files
+   pub fn mmap(&self) -> bool {
+       self.mmap
+   }
+
+   /// Phred quality assumed for bases from FASTA input
+   pub fn fasta_qual(&self) -> u8 {
+       self.fasta_qual
+   }
+
+   /// Reads are full length single molecule reads in either orientation
+   pub fn long_reads(&self) -> bool {
+       self.long_reads
+   }
+
+   /// Maximum length divergence of long reads as a fraction of amplicon length
+   pub fn long_read_length_window(&self) -> f64 {
+       self.long_read_length_window
+   }
+
+   /// Maximum unaligned sequence at the end of read 1 and start of read 2 when merging
+   pub fn merge_free_ends(&self) -> u32 {
+       self.merge_free_ends
+   }
+
+   /// Penalties (match, mismatch, gap open, gap extend) for merging read pairs
+   pub fn merge_penalties(&self) -> [i32; 4] {
+       self.merge_penalties
+   }
+
+   /// Penalties (match, mismatch, gap open, gap extend) for aligning to the reference
+   pub fn ref_penalties(&self) -> [i32; 4] {
+       self.ref_penalties
+   }
+
+   /// Join read pairs that can not be merged using their alignments to the reference
+   pub fn join_unmerged(&self) -> bool {
+       self.join_unmerged
+   }
+
+   /// Trim adapter read through from reads
+   pub fn trim_adapters(&self) -> bool {
+       self.trim_adapters
+   }
+
+   /// Adapter sequences for read 1 and read 2 (if supplied)
+   pub fn adapters(&self) -> Option<&[Vec<u8>; 2]> {
+       self.adapters.as_ref()
+   }
+
+   /// Forward and (reverse complemented) reverse primer sequences (if supplied)
+   pub fn primers(&self) -> Option<&[Vec<u8>; 2]> {
+       self.primers.as_ref()
+   }
+
+   /// Maximum number of invalid records to skip (0 if invalid records are not allowed)
+   pub fn max_bad_records(&self) -> u64 {
+       self.max_bad_records
+   }
    
    pub fn max_overlap_divergence(&self) -> u32 {
        self.max_overlap_divergence
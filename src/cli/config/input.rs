@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+/// Layout of reads in the input files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Read 1 and read 2 in separate files
+    Paired,
+    /// Read 1 and read 2 alternating in a single file
+    Interleaved,
+    /// Single-end or already merged reads
+    Single,
+}
+
+impl InputMode {
+    /// Number of files per input
+    pub fn files_per_input(&self) -> usize {
+        match self {
+            Self::Paired => 2,
+            Self::Interleaved | Self::Single => 1,
+        }
+    }
+}
+
+/// Input file(s) for one lane of a sample
+pub struct Input {
+    sample: usize,
+    files: Vec<PathBuf>,
+    sizes: Vec<Option<u64>>,
+}
+
+impl Input {
+    pub(super) fn new(sample: usize, files: Vec<PathBuf>) -> Self {
+        let sizes = files.iter().map(|f| file_size(f)).collect();
+        Self {
+            sample,
+            files,
+            sizes,
+        }
+    }
+
+    pub fn sample(&self) -> usize {
+        self.sample
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|p| p.as_path())
+    }
+
+    /// Total size of input files, or None if any of the inputs is a stream
+    pub fn size(&self) -> Option<u64> {
+        self.sizes.iter().copied().sum()
+    }
+
+    /// True if any of the inputs is a stream (stdin, pipe etc.) rather than a regular file
+    pub fn is_stream(&self) -> bool {
+        self.sizes.iter().any(|s| s.is_none())
+    }
+}
+
+/// Path used to signify stdin
+pub fn is_stdin(p: &Path) -> bool {
+    p.as_os_str() == "-"
+}
+
+/// Size of regular file; None for stdin or anything else that is not a regular file
+fn file_size(p: &Path) -> Option<u64> {
+    if is_stdin(p) {
+        None
+    } else {
+        p.metadata().ok().filter(|m| m.is_file()).map(|m| m.len())
+    }
+}
@@ -1,59 +1,186 @@
 use std::{
+    collections::HashSet,
     io::BufRead,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context;
-use clap::ArgMatches;
+use clap::{ArgMatches, parser::ValueSource};
 use compress_io::compress::CompressIo;
 
-use super::Config;
+use crate::cli::{iupac_compatible, iupac_complement, iupac_mask};
+
+use super::{
+    Amplicon, Config, Input, InputFormat, InputMode, Pairing,
+    discover::{pair_files, read_input_dir},
+    is_stdin,
+    sample_sheet::read_sample_sheet,
+};
 
 impl Config {
     pub fn from_matches(m: &ArgMatches) -> anyhow::Result<Self> {
-        let input_files: Vec<_> = {
+        let input_format = m
+            .get_one::<InputFormat>("input_format")
+            .copied()
+            .expect("Missing default for input_format");
+
+        // With SAM/BAM input, both reads of a pair come from the same file.  FASTA input
+        // is taken to be single-end (or already merged) reads
+        if input_format == InputFormat::Fasta && m.get_flag("interleaved") {
+            return Err(anyhow!("FASTA input can not be interleaved"));
+        }
+        // Long reads are not merged, so are treated as single-end
+        let long_reads = m.get_flag("long_reads");
+        let input_mode = if m.get_flag("single_end")
+            || input_format == InputFormat::Fasta
+            || long_reads
+        {
+            InputMode::Single
+        } else if m.get_flag("interleaved") || input_format.is_sam() {
+            InputMode::Interleaved
+        } else {
+            InputMode::Paired
+        };
+
+        let (samples, inputs) = if let Some(p) = m.get_one::<PathBuf>("sample_sheet") {
+            read_sample_sheet(p, input_mode)?
+        } else if let Some(p) = m.get_one::<PathBuf>("input_dir") {
+            if input_mode != InputMode::Paired {
+                return Err(anyhow!("Input directory can only be used with paired FASTQ input"));
+            }
+            read_input_dir(p)?
+        } else {
             let x = m
                 .get_many::<PathBuf>("input")
                 .expect("Missing required inputs");
 
             let mut v = x.map(|y| y.to_owned()).collect::<Vec<_>>();
-            if (v.len() & 1) != 0 {
-                return Err(anyhow!(
-                    "Number of input files is not even (expecting pairs"
-                ));
-            }
-            v.sort_unstable();
-            v
+            let inputs = if input_mode == InputMode::Paired {
+                if (v.len() & 1) != 0 {
+                    return Err(anyhow!(
+                        "Number of input files is not even (expecting pairs"
+                    ));
+                }
+                pair_inputs(v)?
+                    .into_iter()
+                    .map(|f| Input::new(0, f.into()))
+                    .collect()
+            } else {
+                v.drain(..).map(|f| Input::new(0, vec![f])).collect()
+            };
+            (Vec::new(), inputs)
         };
 
+        // stdin can only be used once, and not for paired input
+        let n_stdin = inputs
+            .iter()
+            .flat_map(|i| i.files())
+            .filter(|f| is_stdin(f))
+            .count();
+        if n_stdin > 1 {
+            return Err(anyhow!("stdin can only be used for one input"));
+        } else if n_stdin > 0 && input_mode == InputMode::Paired {
+            return Err(anyhow!(
+                "stdin can only be used with interleaved or single-end input"
+            ));
+        }
+
         let threads = m
             .get_one::<u64>("threads")
             .map(|x| *x as usize)
             .unwrap_or_else(num_cpus::get);
 
-        let num_files = input_files.len() >> 1;
+        let num_files = inputs.len();
+
+        // Streams (stdin, pipes) have to be read concurrently, as the writer(s) may be blocked
+        // waiting for them to be consumed, so we need at least one reader per stream
+        let n_streams = inputs.iter().filter(|i| i.is_stream()).count();
 
         let ignore_multibase_deletions = m.get_flag("ignore_multibase_deletions");
         let ignore_multiple_deletions = m.get_flag("ignore_multiple_deletions");
         let ignore_multiple_mutations = m.get_flag("ignore_multiple_mutations");
         let ignore_multiple_modifications = m.get_flag("ignore_multiple_modifications");
         let view_file = m.get_flag("view");
+        let allow_unequal_records = m.get_flag("allow_unequal_records");
+        let read_ahead = !m.get_flag("no_read_ahead");
+        let mmap = m.get_flag("mmap");
+        let fasta_qual = m
+            .get_one::<u8>("fasta_qual")
+            .copied()
+            .expect("Missing default for fasta_qual");
+        let max_bad_records = m
+            .get_one::<u64>("max_bad_records")
+            .copied()
+            .expect("Missing default for max_bad_records");
+        let pairing = m
+            .get_one::<Pairing>("pairing")
+            .copied()
+            .expect("Missing default for pairing");
 
-        let readers = m
-            .get_one::<u64>("readers")
-            .map(|x| *x as usize)
-            .unwrap_or_else(|| {
+        let readers = match m.get_one::<u64>("readers").map(|x| *x as usize) {
+            Some(r) if r < n_streams => {
+                warn!("Increasing number of readers to {n_streams} (one per input stream)");
+                n_streams
+            }
+            Some(r) => r,
+            None => {
                 // No point having more readers than files or physical cores
                 let i = num_cpus::get_physical().min(num_files);
                 // One reader should easily be able to supply 4 process threads
                 let j = (threads >> 2).max(1);
-                i.min(j)
-            });
+                i.min(j).max(n_streams)
+            }
+        };
+
+        // Each reader needs two buffers at once
+        let min_buffers = readers << 1;
+        let buffers = match m.get_one::<u64>("buffers").map(|x| *x as usize) {
+            Some(n) if n < min_buffers => {
+                warn!("Increasing number of buffers to {min_buffers} (two per reader)");
+                min_buffers
+            }
+            Some(n) => n,
+            None => (threads.max(readers) << 2).max(min_buffers),
+        };
+
+        // Buffer sizes are given in KiB
+        let buffer_size = m
+            .get_one::<u64>("buffer_size")
+            .map(|x| (*x as usize) << 10)
+            .expect("Missing default for buffer_size");
+        let max_buffer_size = m
+            .get_one::<u64>("max_buffer_size")
+            .map(|x| (*x as usize) << 10)
+            .expect("Missing default for max_buffer_size");
+        if max_buffer_size < buffer_size {
+            return Err(anyhow!(
+                "Maximum buffer size can not be less than the initial buffer size"
+            ));
+        }
+        // Buffers only grow to fit long records, and return to their initial size when they are
+        // drained, but in the worst case they could all be at the maximum size at once
+        info!(
+            "Memory used by input buffers: {} MiB (up to {} MiB if buffers grow to fit long records)",
+            (buffers * buffer_size) >> 19,
+            (buffers * max_buffer_size) >> 19
+        );
 
         let min_qual = m
             .get_one::<u8>("min_qual")
             .copied()
             .expect("Missing default for min_qual");
+        let qual_offset = m
+            .get_one::<String>("qual_offset")
+            .filter(|s| s.as_str() != "auto")
+            .map(|s| s.parse::<u8>().expect("Invalid qual_offset"))
+            .filter(|_| {
+                // Converted inputs (FASTA/SAM/BAM) always have Phred+33 qualities
+                let fastq = input_format == InputFormat::Fastq;
+                if !fastq {
+                    warn!("Quality offset is ignored for non FASTQ input");
+                }
+                fastq
+            });
         let max_overlap_divergence = m
             .get_one::<u32>("max_overlap_divergence")
             .copied()
@@ -62,22 +189,105 @@ impl Config {
             .get_one::<u32>("max_length_divergence")
             .copied()
             .expect("Missing default for min_qual");
+        let penalties = |opt: &str| {
+            parse_penalties(m.get_one::<String>(opt).expect("Missing default for penalties"))
+            .with_context(|| format!("Invalid value for --{}", opt.replace('_', "-")))
+        };
+        // Long reads have their own penalties for alignment to the reference
+        let ref_penalties = if long_reads {
+            penalties("long_read_penalties")?
+        } else {
+            penalties("ref_penalties")?
+        };
+        let merge_penalties = penalties("merge_penalties")?;
+        let join_unmerged = m.get_flag("join_unmerged");
+        let seq_opt = |opt: &str| {
+            m.get_one::<String>(opt)
+                .map(|s| {
+                    parse_seq(s)
+                        .with_context(|| format!("Invalid sequence for --{}", opt.replace('_', "-")))
+                })
+                .transpose()
+        };
+        let adapters = match (seq_opt("adapter")?, seq_opt("adapter2")?) {
+            (Some(a1), Some(a2)) => Some([a1, a2]),
+            (Some(a), None) => Some([a.clone(), a]),
+            _ => None,
+        };
+        let trim_adapters = m.get_flag("trim_adapters") || adapters.is_some();
+        // The reverse primer is stored reverse complemented, as it appears at the end of reads
+        let primers = match (seq_opt("forward_primer")?, seq_opt("reverse_primer")?) {
+            (Some(p1), Some(p2)) => Some([p1, revcomp(&p2)]),
+            _ => None,
+        };
+        if trim_adapters && adapters.is_none() && input_mode == InputMode::Single {
+            return Err(anyhow!(
+                "An adapter sequence (--adapter) is required to trim single-end reads"
+            ));
+        }
+        let merge_free_ends = m
+            .get_one::<u32>("merge_free_ends")
+            .copied()
+            .expect("Missing default for merge_free_ends");
+        // Merge options have no effect if reads are not merged
+        if input_mode == InputMode::Single {
+            for opt in ["merge_free_ends", "merge_penalties", "join_unmerged"] {
+                if m.value_source(opt) == Some(ValueSource::CommandLine) {
+                    return Err(anyhow!(
+                        "--{} can not be used with single-end input",
+                        opt.replace('_', "-")
+                    ));
+                }
+            }
+        }
+        let long_read_length_window = m
+            .get_one::<f64>("long_read_length_window")
+            .copied()
+            .expect("Missing default for long_read_length_window");
+        if !long_read_length_window.is_finite() || long_read_length_window < 0.0 {
+            return Err(anyhow!("Long read length window must not be negative"));
+        }
         let output_prefix = m
             .get_one::<String>("output_prefix")
             .map(|s| s.to_owned())
             .expect("Missing default for output_prefix");
 
-        let reference = read_reference(
+        let mut amplicons = read_reference(
             m.get_one::<PathBuf>("reference")
                 .expect("Missing reference"),
         )?;
+        if let Some(p) = primers.as_ref() {
+            // The primers are the same for all amplicons
+            if amplicons.len() > 1 {
+                return Err(anyhow!(
+                    "Primers (--forward-primer, --reverse-primer) can not be used with more than one reference record"
+                ));
+            }
+            for a in amplicons.iter_mut() {
+                set_primer_target(a, p)?
+            }
+        }
+        let max_amplicon_len = amplicons.iter().map(|a| a.seq().len()).max().unwrap_or(0);
+        if input_mode != InputMode::Single && merge_free_ends as usize > max_amplicon_len {
+            return Err(anyhow!(
+                "Merge free end window ({merge_free_ends}) is longer than the longest amplicon ({max_amplicon_len})"
+            ));
+        }
         Ok(Self {
             min_qual,
+            qual_offset,
             output_prefix,
             threads,
             readers,
-            reference,
-            input_files,
+            buffers,
+            buffer_size,
+            max_buffer_size,
+            amplicons,
+            samples,
+            input_format,
+            input_mode,
+            inputs,
+            pairing,
             max_length_divergence,
             max_overlap_divergence,
             ignore_multibase_deletions,
@@ -85,11 +295,104 @@ impl Config {
             ignore_multiple_deletions,
             ignore_multiple_modifications,
             view_file,
+            allow_unequal_records,
+            max_bad_records,
+            read_ahead,
+            mmap,
+            fasta_qual,
+            long_reads,
+            long_read_length_window,
+            merge_free_ends,
+            merge_penalties,
+            ref_penalties,
+            join_unmerged,
+            trim_adapters,
+            adapters,
+            primers,
         })
     }
 }
 
-fn read_reference(p: &Path) -> anyhow::Result<Vec<u8>> {
+/// Pair input files given on the command line by name if possible.  Otherwise (for example
+/// with streams such as `/dev/fd/63`) files are paired in command line order
+fn pair_inputs(v: Vec<PathBuf>) -> anyhow::Result<Vec<[PathBuf; 2]>> {
+    Ok(match pair_files(v.clone())? {
+        Some(s) => s.into_iter().flat_map(|(_, p)| p).collect(),
+        None => {
+            warn!("Input file names not recognized - pairing files in command line order");
+            let mut pairs = Vec::with_capacity(v.len() >> 1);
+            let mut itr = v.into_iter();
+            while let (Some(f1), Some(f2)) = (itr.next(), itr.next()) {
+                pairs.push([f1, f2])
+            }
+            pairs
+        }
+    })
+}
+
+/// Parse adapter or primer sequence, converting to upper case.  IUPAC degenerate codes
+/// (R, Y, K, M, S, W, B, D, H, V, N) are accepted
+fn parse_seq(s: &str) -> anyhow::Result<Vec<u8>> {
+    let v = s.as_bytes().to_ascii_uppercase();
+    if v.is_empty() || !v.iter().all(|c| iupac_mask(*c) != 0) {
+        Err(anyhow!("Expecting a non-empty sequence of IUPAC nucleotide codes"))
+    } else {
+        Ok(v)
+    }
+}
+
+fn revcomp(s: &[u8]) -> Vec<u8> {
+    s.iter().rev().map(|c| iupac_complement(*c)).collect()
+}
+
+/// Restrict the target region of amplicon `a` to exclude the forward primer (`primers[0]`) if
+/// present at the start of the reference and the reverse complemented reverse primer
+/// (`primers[1]`) if present at the end
+fn set_primer_target(a: &mut Amplicon, primers: &[Vec<u8>; 2]) -> anyhow::Result<()> {
+    let [p1, p2] = primers;
+    let s = a.seq();
+    let same = |x: &[u8], p: &[u8]| x.iter().zip(p).all(|(c, d)| iupac_compatible(*c, *d));
+    let l = s.len();
+    let start = if l >= p1.len() && same(&s[..p1.len()], p1) { p1.len() } else { 0 };
+    let end = if l >= p2.len() && same(&s[l - p2.len()..], p2) { l - p2.len() } else { l };
+    if end < start + 2 {
+        return Err(anyhow!("Primers cover all of reference record {}", a.name()));
+    }
+    debug!(
+        "Reference {}: target region (excluding primers) {}-{}",
+        a.name(),
+        start + 1,
+        end
+    );
+    a.set_target(start..end);
+    Ok(())
+}
+
+/// Parse alignment penalties given as `match,mismatch,gap_open,gap_extend`
+fn parse_penalties(s: &str) -> anyhow::Result<[i32; 4]> {
+    let v = s
+        .split(',')
+        .map(|x| x.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Could not parse penalties from {s}"))?;
+    let p: [i32; 4] = v
+        .try_into()
+        .map_err(|_| anyhow!("Expected four penalties (match,mismatch,gap open,gap extend)"))?;
+    if p[0] != 0 || p[1] <= 0 || p[2] < 0 || p[3] <= 0 {
+        return Err(anyhow!(
+            "Match penalty must be zero, mismatch and gap extend penalties must be positive, and gap open penalty must not be negative"
+        ));
+    }
+    // Otherwise mismatches would always be aligned as an insertion plus a deletion
+    if p[1] >= (p[2] + p[3]) << 1 {
+        return Err(anyhow!(
+            "Mismatch penalty must be less than the penalty for an insertion plus a deletion"
+        ));
+    }
+    Ok(p)
+}
+
+fn read_reference(p: &Path) -> anyhow::Result<Vec<Amplicon>> {
     debug!("Opening reference file");
 
     let mut rdr = CompressIo::new()
@@ -100,8 +403,9 @@ fn read_reference(p: &Path) -> anyhow::Result<Vec<u8>> {
     info!("Reading from reference file {}", p.display());
 
     let mut s = String::new();
+    let mut amplicons = Vec::new();
+    let mut name: Option<String> = None;
     let mut rf = Vec::new();
-    let mut first = true;
 
     loop {
         let l = rdr
@@ -110,14 +414,82 @@ fn read_reference(p: &Path) -> anyhow::Result<Vec<u8>> {
         if l == 0 {
             break;
         }
-        if s.starts_with('>') {
-            if first { first = false } else { break }
-        } else {
+        if let Some(h) = s.strip_prefix('>') {
+            if let Some(n) = name.take() {
+                amplicons.push(mk_amplicon(n, std::mem::take(&mut rf))?)
+            }
+            let n = h
+                .split_ascii_whitespace()
+                .next()
+                .ok_or_else(|| anyhow!("Missing name for reference record"))?;
+            // The name is used in output file names, so other characters are replaced
+            let safe: String = n
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            if safe != n {
+                warn!("Reference record name {n} changed to {safe} for use in output file names")
+            }
+            name = Some(safe)
+        } else if name.is_some() {
             rf.extend_from_slice(s.trim_end().as_bytes());
+        } else if !s.trim().is_empty() {
+            return Err(anyhow!("Reference file does not start with a FASTA header"));
         }
         s.clear();
     }
+    if let Some(n) = name.take() {
+        amplicons.push(mk_amplicon(n, rf)?)
+    }
+
+    if amplicons.is_empty() {
+        return Err(anyhow!("No records found in reference file {}", p.display()));
+    }
+
+    let mut names = HashSet::new();
+    for a in amplicons.iter() {
+        if !names.insert(a.name()) {
+            return Err(anyhow!("Duplicate reference record name {}", a.name()));
+        }
+    }
+
+    info!("Read {} reference record(s)", amplicons.len());
+    Ok(amplicons)
+}
+
+fn mk_amplicon(name: String, seq: Vec<u8>) -> anyhow::Result<Amplicon> {
+    if seq.len() < 2 {
+        Err(anyhow!("Reference record {name} is too short"))
+    } else {
+        debug!("Read reference {name} ({} bases)", seq.len());
+        Ok(Amplicon::new(name, seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    info!("Read reference ({} bases)", rf.len());
-    Ok(rf)
+    #[test]
+    fn pair_unrecognised_in_command_line_order() {
+        let v = ["/dev/fd/63", "/dev/fd/62", "b.fq", "a.fq"].map(PathBuf::from);
+        let pairs = pair_inputs(v.to_vec()).unwrap();
+        assert_eq!(
+            pairs,
+            vec![[v[0].clone(), v[1].clone()], [v[2].clone(), v[3].clone()]]
+        );
+    }
+
+    #[test]
+    fn pair_recognised_by_name() {
+        let v = ["s_S1_R2_001.fastq.gz", "s_S1_R1_001.fastq.gz"].map(PathBuf::from);
+        let pairs = pair_inputs(v.to_vec()).unwrap();
+        assert_eq!(pairs, vec![[v[1].clone(), v[0].clone()]]);
+    }
 }
@@ -0,0 +1,138 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use super::{Input, InputMode, is_stdin};
+
+/// Read sample sheet
+///
+/// The sample sheet is a tab or comma separated file with either 3 columns (sample, R1, R2)
+/// or 4 columns (sample, lane, R1, R2).  For interleaved or single-end input there is a single file column
+/// in place of R1 and R2.  An optional header line (first column 'sample') is
+/// skipped, as are blank lines and lines starting with '#'.  Multiple lanes for a sample
+/// are given as separate lines with distinct lane identifiers.
+///
+/// Returns the list of sample names and the list of inputs
+pub(super) fn read_sample_sheet(
+    p: &Path,
+    mode: InputMode,
+) -> anyhow::Result<(Vec<String>, Vec<Input>)> {
+    debug!("Opening sample sheet");
+
+    let mut rdr = CompressIo::new()
+        .path(p)
+        .bufreader()
+        .with_context(|| format!("Could not open sample sheet {}", p.display()))?;
+
+    info!("Reading from sample sheet {}", p.display());
+
+    let mut s = String::new();
+    let mut samples = Vec::new();
+    let mut sample_hash = HashMap::new();
+    let mut lanes = HashSet::new();
+    let mut files = HashSet::new();
+    let mut inputs = Vec::new();
+    let mut n_cols = None;
+    let mut line = 0;
+    let nf = mode.files_per_input();
+
+    loop {
+        s.clear();
+        let l = rdr
+            .read_line(&mut s)
+            .with_context(|| "Error reading from sample sheet")?;
+        if l == 0 {
+            break;
+        }
+        line += 1;
+        let t = s.trim();
+        if t.is_empty() || t.starts_with('#') {
+            continue;
+        }
+        let sep = if t.contains('\t') { '\t' } else { ',' };
+        let fields: Vec<_> = t.split(sep).map(|f| f.trim()).collect();
+
+        if fields.len() != nf + 1 && fields.len() != nf + 2 {
+            return Err(anyhow!(
+                "Sample sheet line {line}: expecting {} or {} columns, found {}",
+                nf + 1,
+                nf + 2,
+                fields.len()
+            ));
+        }
+        match n_cols {
+            None => {
+                n_cols = Some(fields.len());
+                if fields[0].eq_ignore_ascii_case("sample") {
+                    continue;
+                }
+            }
+            Some(n) if n != fields.len() => {
+                return Err(anyhow!(
+                    "Sample sheet line {line}: inconsistent number of columns"
+                ));
+            }
+            _ => {}
+        }
+
+        let (name, lane, fnames) = if fields.len() == nf + 2 {
+            (fields[0], fields[1], &fields[2..])
+        } else {
+            (fields[0], "", &fields[1..])
+        };
+
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(anyhow!("Sample sheet line {line}: invalid sample name '{name}'"));
+        }
+        if !lanes.insert((name.to_owned(), lane.to_owned())) {
+            return Err(if lane.is_empty() {
+                anyhow!("Sample sheet line {line}: duplicate sample {name}")
+            } else {
+                anyhow!("Sample sheet line {line}: duplicate lane {lane} for sample {name}")
+            });
+        }
+        let fnames: Vec<_> = fnames.iter().map(PathBuf::from).collect();
+        if nf == 2 && fnames[0] == fnames[1] {
+            return Err(anyhow!(
+                "Sample sheet line {line}: R1 and R2 are the same file ({})",
+                fnames[0].display()
+            ));
+        }
+        for f in fnames.iter() {
+            if !(is_stdin(f) || f.exists()) {
+                return Err(anyhow!(
+                    "Sample sheet line {line}: input file {} not found",
+                    f.display()
+                ));
+            }
+            if !files.insert(f.clone()) {
+                return Err(anyhow!(
+                    "Sample sheet line {line}: input file {} used more than once",
+                    f.display()
+                ));
+            }
+        }
+
+        let ix = *sample_hash.entry(name.to_owned()).or_insert_with(|| {
+            samples.push(name.to_owned());
+            samples.len() - 1
+        });
+        inputs.push(Input::new(ix, fnames));
+    }
+
+    if inputs.is_empty() {
+        return Err(anyhow!("No samples found in sample sheet {}", p.display()));
+    }
+
+    info!(
+        "Read {} sample(s) with {} input(s) from sample sheet",
+        samples.len(),
+        inputs.len()
+    );
+    Ok((samples, inputs))
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+use clap::{builder::PossibleValue, ValueEnum};
+
+/// InputFormat
+///
+/// Format of the input read files
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Fastq,
+    Fasta,
+    Sam,
+    Bam,
+}
+
+impl ValueEnum for InputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Fastq, Self::Fasta, Self::Sam, Self::Bam]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Self::Fastq => Some(PossibleValue::new("fastq")),
+            Self::Fasta => Some(PossibleValue::new("fasta")),
+            Self::Sam => Some(PossibleValue::new("sam")),
+            Self::Bam => Some(PossibleValue::new("bam")),
+        }
+    }
+}
+
+impl InputFormat {
+    /// True for formats where both reads of a pair come from the same file
+    pub fn is_sam(&self) -> bool {
+        matches!(self, Self::Sam | Self::Bam)
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Fastq => "fastq",
+            Self::Fasta => "fasta",
+            Self::Sam => "sam",
+            Self::Bam => "bam",
+        };
+        write!(f, "{s}")
+    }
+}
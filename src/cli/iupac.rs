@@ -0,0 +1,48 @@
+/// Bases matched by an IUPAC nucleotide code as a bit mask (A = 1, C = 2, G = 4, T = 8), or 0
+/// if `c` is not a valid code.  Lower case codes are accepted
+pub fn iupac_mask(c: u8) -> u8 {
+    match c.to_ascii_uppercase() {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' | b'U' => 8,
+        b'M' => 3,
+        b'R' => 5,
+        b'W' => 9,
+        b'S' => 6,
+        b'Y' => 10,
+        b'K' => 12,
+        b'V' => 7,
+        b'H' => 11,
+        b'D' => 13,
+        b'B' => 14,
+        b'N' => 15,
+        _ => 0,
+    }
+}
+
+/// Check whether two IUPAC codes (e.g., a read base and a primer base) can match the same
+/// base.  `N` matches everything
+#[inline]
+pub fn iupac_compatible(a: u8, b: u8) -> bool {
+    iupac_mask(a) & iupac_mask(b) != 0
+}
+
+/// Complement of an (upper case) IUPAC code
+pub fn iupac_complement(c: u8) -> u8 {
+    match c {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' | b'U' => b'A',
+        b'M' => b'K',
+        b'K' => b'M',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'V' => b'B',
+        b'B' => b'V',
+        b'H' => b'D',
+        b'D' => b'H',
+        x => x,
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use clap::{builder::PossibleValue, ValueEnum};
+
+/// Pairing
+///
+/// Rule used to check that the IDs of read 1 and read 2 match
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pairing {
+    /// First whitespace delimited token of the IDs must be identical
+    Strict,
+    /// As Strict, after removing /1 and /2 suffixes
+    StripSuffix,
+    /// Compare only the Illumina instrument:run:flowcell:lane:tile:x:y prefix
+    Illumina,
+    /// No check
+    None,
+}
+
+impl ValueEnum for Pairing {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Strict, Self::StripSuffix, Self::Illumina, Self::None]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Self::Strict => Some(PossibleValue::new("strict")),
+            Self::StripSuffix => Some(PossibleValue::new("strip-suffix")),
+            Self::Illumina => Some(PossibleValue::new("illumina")),
+            Self::None => Some(PossibleValue::new("none")),
+        }
+    }
+}
+
+impl fmt::Display for Pairing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Strict => "strict",
+            Self::StripSuffix => "strip-suffix",
+            Self::Illumina => "illumina",
+            Self::None => "none",
+        };
+        write!(f, "{s}")
+    }
+}
@@ -1,7 +1,9 @@
 mod align;
 mod counts;
 mod fastq;
+mod orient;
 mod process_threads;
+mod read_id;
 mod reader;
 mod view;
 mod write_view;
@@ -10,6 +12,7 @@ use crossbeam_channel::{bounded, unbounded};
 
 pub use fastq::FastQIter;
 use process_threads::process_threads;
+use view::SampleProgress;
 
 pub use reader::Buffer;
 
@@ -20,6 +23,8 @@ use super::cli::Config;
 pub fn process(cfg: &Config) -> anyhow::Result<()> {
     let mut stats = None;
     let mut error = None;
+    // Used to close view files when a sample is complete
+    let progress = cfg.view_file().then(|| SampleProgress::new(cfg));
  
     thread::scope(|scope| {
         // Channel used to send full buffers to process threads
@@ -44,15 +49,17 @@ pub fn process(cfg: &Config) -> anyhow::Result<()> {
 
         let rx = full_recv.clone();
         let tx = empty_send.clone();
-        let tx_view = view_chan.as_ref().map(|(t,_)| {
-            t.clone()
+        // View record senders for the process threads and readers
+        let tx_view = || view_chan.as_ref().zip(progress.as_ref()).map(|((t, _), p)| {
+            (t.clone(), p)
         });
-        let process_handle = scope.spawn(|| process_threads(cfg, rx, tx, tx_view));
+        let views = tx_view();
+        let process_handle = scope.spawn(|| process_threads(cfg, rx, tx, views));
 
         drop(full_recv);
         drop(empty_send);
 
-        if let Err(e) = reader::reader(cfg, empty_recv, full_send) {
+        if let Err(e) = reader::reader(cfg, empty_recv, full_send, tx_view()) {
             error = Some(anyhow!(e))
         }
 
@@ -75,7 +82,14 @@ pub fn process(cfg: &Config) -> anyhow::Result<()> {
     if let Some(e) = error {
         Err(e)
     } else if let Some(s) = stats.take() {
-        s.output(cfg)
+        for (ix, st) in s.iter().enumerate() {
+            st.output(cfg, ix)?
+        }
+        let n_bad: u64 = s.iter().map(|st| st.n_bad_records()).sum();
+        if n_bad > 0 && s.len() > 1 {
+            warn!("Total of {n_bad} invalid record(s) skipped")
+        }
+        Ok(())
     } else {
         Err(anyhow!("No statistics were collected"))
     }
@@ -4,25 +4,35 @@ use rust_wfa2::{
     error::WfaStatus,
 };
 
+// Affine penalties (match, mismatch, gap open, gap extend)
+const PENALTIES: [i32; 4] = [0, 4, 6, 2];
+
 pub struct Aligner {
     aligner: WfaAligner,
     buf: Vec<u8>,
+    penalties: [i32; 4],
 }
 
 impl Default for Aligner {
     fn default() -> Self {
+        Self::new(PENALTIES)
+    }
+}
+
+impl Aligner {
+    /// Create aligner with affine `penalties` (match, mismatch, gap open, gap extend)
+    pub fn new(penalties: [i32; 4]) -> Self {
         let mut attributes = Attributes::default();
-        attributes.set_affine_penalties(0, 4, 6, 2);
+        attributes.set_affine_penalties(penalties[0], penalties[1], penalties[2], penalties[3]);
         attributes.set_alignment_scope(AlignmentScope::Alignment);
         let aligner = WfaAligner::new(&attributes);
         Self {
             aligner,
             buf: Vec::new(),
+            penalties,
         }
     }
-}
 
-impl Aligner {
     pub fn wfs_aligner(&self) -> &WfaAligner {
         &self.aligner
     }
@@ -44,6 +54,29 @@ impl Aligner {
         self.aligner.align(pattern, text).map_err(|e| anyhow!(e))
     }
 
+    /// Penalty of the last alignment, calculated from the CIGAR operations
+    pub fn cost(&self) -> i64 {
+        self.ops_cost(self.aligner.cigar().operations())
+    }
+
+    /// Penalty of the CIGAR operations `ops`
+    pub fn ops_cost(&self, ops: &[u8]) -> i64 {
+        let [m, x, o, e] = self.penalties.map(|p| p as i64);
+        let mut cost = 0;
+        let mut last = 0;
+        for op in ops {
+            cost += match *op {
+                b'M' => m,
+                b'X' => x,
+                b'I' | b'D' if *op == last => e,
+                b'I' | b'D' => o + e,
+                _ => 0,
+            };
+            last = *op;
+        }
+        cost
+    }
+
     #[inline]
     pub fn set_alignment_free_ends(
         &mut self,
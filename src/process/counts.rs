@@ -3,12 +3,15 @@ use std::{
     collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufWriter, Write},
-    ops::AddAssign,
+    ops::{AddAssign, Range},
 };
 
 use anyhow::Context;
 
-use crate::cli::Config;
+use crate::{
+    cli::{Amplicon, Config},
+    process::fastq::FastQErrorKind,
+};
 
 const COUNTS_N: usize = 6;
 
@@ -27,6 +30,9 @@ impl AddAssign for Counts {
 }
 
 pub struct Stats<'a> {
+    rf: &'a [u8],
+    // Reference positions (excluding primers) that are output
+    target: Range<usize>,
     pos_counts: Vec<Counts>,
     insert_len: InsertLength,
     mut_corr: MutCorr<'a>,
@@ -73,7 +79,9 @@ impl<'a> AddAssign for Stats<'a> {
 }
 
 impl<'a> Stats<'a> {
-    pub fn new(rf: &'a [u8]) -> Self {
+    pub fn new(amplicon: &'a Amplicon) -> Self {
+        let rf = amplicon.seq();
+        let target = amplicon.target_range();
         let size = rf.len();
         let pos_counts: Vec<_> = (0..size).map(|_| Counts::default()).collect();
         let insert_len = InsertLength::default();
@@ -84,6 +92,8 @@ impl<'a> Stats<'a> {
         let mod_count_hist = BTreeMap::new();
         let del_len_hist = BTreeMap::new();
         Self {
+            rf,
+            target,
             pos_counts,
             insert_len,
             mut_corr,
@@ -135,13 +145,13 @@ impl<'a> Stats<'a> {
         self.insert_len.add_len(len)
     }
 
-    pub fn output(&self, cfg: &Config) -> anyhow::Result<()> {
-        let out_name = format!("{}_stats.tsv", cfg.output_prefix());
+    pub fn output(&self, prefix: &str) -> anyhow::Result<()> {
+        let out_name = format!("{prefix}_stats.tsv");
         let mut wrt = BufWriter::new(
             File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
         );
 
-        let rf = cfg.reference();
+        let rf = self.rf;
 
         writeln!(
             wrt,
@@ -154,6 +164,7 @@ impl<'a> Stats<'a> {
             .map(|c| &c.base_counts)
             .zip(rf.iter())
             .enumerate()
+            .filter(|(ix, _)| self.target.contains(ix))
         {
             write!(wrt, "{}\t{}", ix + 1, *r as char)?;
             let n = ct[..COUNTS_N - 1].iter().sum::<u64>();
@@ -181,7 +192,7 @@ impl<'a> Stats<'a> {
             writeln!(wrt, "\t{:.2}\t{:.2}", mm as f64 * 100.0 / n, (mm + ct[4] + ct[5]) as f64 * 100.0 / n)?;
         }
 
-        let out_name = format!("{}_mut_and_del_stats.tsv", cfg.output_prefix());
+        let out_name = format!("{prefix}_mut_and_del_stats.tsv");
         let mut wrt = BufWriter::new(
             File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
         );
@@ -213,12 +224,13 @@ impl<'a> Stats<'a> {
                 del_len as f64 * 100.0 / n_dels,
             )?;
         }
-        self.insert_len.output(cfg)?;
-        let cm1 = self.mut_corr.output(cfg)?;
+        self.insert_len
+            .output(&format!("{prefix}_insert_len.tsv"))?;
+        let cm1 = self.mut_corr.output(prefix, &self.target)?;
 
-        self.output_del(cfg)?;
-        let cm = self.mk_del_cm(cfg.reference().len());
-        self.output_cm(cfg, &cm, &cm1)
+        self.output_del(prefix)?;
+        let cm = self.mk_del_cm(rf.len());
+        self.output_cm(prefix, &cm, &cm1)
     }
 
     fn mk_del_cm(&self, ref_len: usize) -> Vec<usize> {
@@ -234,16 +246,16 @@ impl<'a> Stats<'a> {
         cm
     }
 
-    fn output_cm(&self, cfg: &Config, cm: &[usize], cm1: &[[f64; 2]]) -> anyhow::Result<()> {
-        let out_name = format!("{}_contact_map.tsv", cfg.output_prefix());
+    fn output_cm(&self, prefix: &str, cm: &[usize], cm1: &[[f64; 2]]) -> anyhow::Result<()> {
+        let out_name = format!("{prefix}_contact_map.tsv");
         let mut wrt = BufWriter::new(
             File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
         );
         writeln!(wrt, "x\ty\tdel%\tmm%\tr")?;
         let tot = self.n_reads[1] as f64;
-        let l = cfg.reference().len();
-        for x in 0..l {
-            for y in 0..l {
+        let l = self.rf.len();
+        for x in self.target.clone() {
+            for y in self.target.clone() {
                 let z = &cm1[x * l + y];
                 writeln!(
                     wrt,
@@ -260,7 +272,7 @@ impl<'a> Stats<'a> {
         Ok(())
     }
 
-    fn output_del(&self, cfg: &Config) -> anyhow::Result<()> {
+    fn output_del(&self, prefix: &str) -> anyhow::Result<()> {
         let mut v: Vec<_> = self.del_hash.iter().collect();
         v.sort_unstable_by(|((a1, b1), x1), ((a2, b2), x2)| match x2.cmp(x1) {
             Ordering::Equal => (b1 - a1).cmp(&(b2 - a2)),
@@ -269,7 +281,7 @@ impl<'a> Stats<'a> {
 
         let tot = self.n_reads[1] as f64;
 
-        let out_name = format!("{}_del.tsv", cfg.output_prefix());
+        let out_name = format!("{prefix}_del.tsv");
         let mut wrt = BufWriter::new(
             File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
         );
@@ -291,6 +303,236 @@ impl<'a> Stats<'a> {
     }
 }
 
+/// Statistics for a sample: per amplicon statistics, plus the length distribution of merged reads
+/// that could not be assigned to any amplicon.  Amplicon statistics are allocated on first use
+pub struct SampleStats<'a> {
+    amplicons: &'a [Amplicon],
+    stats: Vec<Option<Stats<'a>>>,
+    unmatched: InsertLength,
+    id_suffixes: u64,
+    reversed: u64,
+    joined: u64,
+    adapter_trimmed: u64,
+    missing_primers: u64,
+    short: u64,
+    trimmed_short: u64,
+    bad_records: BadRecords,
+}
+
+impl<'a> AddAssign for SampleStats<'a> {
+    fn add_assign(&mut self, mut rhs: Self) {
+        assert_eq!(self.stats.len(), rhs.stats.len());
+
+        for (s1, s2) in self.stats.iter_mut().zip(rhs.stats.drain(..)) {
+            match (s1.as_mut(), s2) {
+                (Some(x), Some(y)) => *x += y,
+                (None, Some(y)) => *s1 = Some(y),
+                _ => {}
+            }
+        }
+        self.unmatched += rhs.unmatched;
+        self.id_suffixes += rhs.id_suffixes;
+        self.reversed += rhs.reversed;
+        self.joined += rhs.joined;
+        self.adapter_trimmed += rhs.adapter_trimmed;
+        self.missing_primers += rhs.missing_primers;
+        self.short += rhs.short;
+        self.trimmed_short += rhs.trimmed_short;
+        self.bad_records += rhs.bad_records;
+    }
+}
+
+impl<'a> SampleStats<'a> {
+    pub fn new(cfg: &'a Config) -> Self {
+        let amplicons = cfg.amplicons();
+        let stats = amplicons.iter().map(|_| None).collect();
+        Self {
+            amplicons,
+            stats,
+            unmatched: InsertLength::default(),
+            id_suffixes: 0,
+            reversed: 0,
+            joined: 0,
+            adapter_trimmed: 0,
+            missing_primers: 0,
+            short: 0,
+            trimmed_short: 0,
+            bad_records: BadRecords::default(),
+        }
+    }
+
+    #[inline]
+    pub fn amplicon_mut(&mut self, ix: usize) -> &mut Stats<'a> {
+        let a = &self.amplicons[ix];
+        self.stats[ix].get_or_insert_with(|| Stats::new(a))
+    }
+
+    #[inline]
+    pub fn add_unmatched(&mut self, len: u32) {
+        self.unmatched.add_len(len)
+    }
+
+    /// Count read pair where the IDs only matched after removing /1 and /2 suffixes
+    #[inline]
+    pub fn add_id_suffix(&mut self) {
+        self.id_suffixes += 1
+    }
+
+    /// Count long read that was reverse complemented to match the amplicons
+    #[inline]
+    pub fn add_reversed(&mut self) {
+        self.reversed += 1
+    }
+
+    /// Count read pair that did not overlap and was joined with a gap
+    #[inline]
+    pub fn add_joined(&mut self) {
+        self.joined += 1
+    }
+
+    /// Count read (or read pair) where adapter sequence was trimmed
+    #[inline]
+    pub fn add_adapter_trimmed(&mut self) {
+        self.adapter_trimmed += 1
+    }
+
+    /// Count merged read rejected because the expected primers were not found
+    #[inline]
+    pub fn add_missing_primers(&mut self) {
+        self.missing_primers += 1
+    }
+
+    /// Count read rejected as too short to contain the expected primers
+    #[inline]
+    pub fn add_short(&mut self) {
+        self.short += 1
+    }
+
+    /// Count read (or read pair) rejected as too short after adapter trimming
+    #[inline]
+    pub fn add_trimmed_short(&mut self) {
+        self.trimmed_short += 1
+    }
+
+    /// Count record (or record pair) skipped due to a FASTQ format error
+    #[inline]
+    pub fn add_bad_record(&mut self, kind: FastQErrorKind) {
+        self.bad_records.fastq[kind.ix()] += 1
+    }
+
+    /// Count record pair skipped because the read IDs do not match
+    #[inline]
+    pub fn add_bad_id(&mut self) {
+        self.bad_records.id_mismatch += 1
+    }
+
+    /// Total number of skipped records
+    pub fn n_bad_records(&self) -> u64 {
+        self.bad_records.n()
+    }
+
+    pub fn output(&self, cfg: &Config, sample: usize) -> anyhow::Result<()> {
+        let name = cfg.sample_name(sample).map(|s| format!("Sample {s}: ")).unwrap_or_default();
+        if self.id_suffixes > 0 {
+            info!(
+                "{name}{} read pair IDs matched after removing /1 and /2 suffixes",
+                self.id_suffixes
+            );
+        }
+        if self.reversed > 0 {
+            info!("{name}{} reads were reverse complemented", self.reversed);
+        }
+        if self.adapter_trimmed > 0 {
+            info!(
+                "{name}{} reads (or read pairs) had adapter sequence trimmed",
+                self.adapter_trimmed
+            );
+        }
+        if self.trimmed_short > 0 {
+            info!(
+                "{name}{} reads (or read pairs) rejected as too short after adapter trimming",
+                self.trimmed_short
+            );
+        }
+        if self.missing_primers > 0 {
+            info!(
+                "{name}{} merged reads rejected as the expected primers were not found",
+                self.missing_primers
+            );
+        }
+        if self.short > 0 {
+            info!(
+                "{name}{} reads rejected as too short to contain the expected primers",
+                self.short
+            );
+        }
+        if self.joined > 0 {
+            info!("{name}{} read pairs that could not be merged were joined with a gap", self.joined);
+        }
+        self.bad_records.output(&name);
+        for (ix, (s, a)) in self.stats.iter().zip(self.amplicons).enumerate() {
+            let empty;
+            let s = match s {
+                Some(s) => s,
+                None => {
+                    empty = Stats::new(a);
+                    &empty
+                }
+            };
+            info!(
+                "{name}Amplicon {}: {} merged reads, {} used",
+                a.name(),
+                s.n_reads[0],
+                s.n_reads[1]
+            );
+            s.output(&cfg.amplicon_prefix(sample, ix))?
+        }
+        info!(
+            "{name}{} merged reads did not match any amplicon",
+            self.unmatched.n()
+        );
+        self.unmatched
+            .output(&format!("{}_unmatched_len.tsv", cfg.sample_prefix(sample)))
+    }
+}
+
+/// Counts of invalid records (or record pairs) that were skipped, by type of error
+#[derive(Default)]
+struct BadRecords {
+    fastq: [u64; FastQErrorKind::ALL.len()],
+    id_mismatch: u64,
+}
+
+impl AddAssign for BadRecords {
+    fn add_assign(&mut self, rhs: Self) {
+        for (a, b) in self.fastq.iter_mut().zip(rhs.fastq) {
+            *a += b
+        }
+        self.id_mismatch += rhs.id_mismatch
+    }
+}
+
+impl BadRecords {
+    fn n(&self) -> u64 {
+        self.fastq.iter().sum::<u64>() + self.id_mismatch
+    }
+
+    fn output(&self, name: &str) {
+        let n = self.n();
+        if n > 0 {
+            warn!("{name}{n} invalid record(s) skipped");
+            for (k, ct) in FastQErrorKind::ALL.iter().zip(self.fastq) {
+                if ct > 0 {
+                    warn!("{name}    {k}: {ct}")
+                }
+            }
+            if self.id_mismatch > 0 {
+                warn!("{name}    read IDs do not match: {}", self.id_mismatch)
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct InsertLength {
     hash: BTreeMap<u32, u64>,
@@ -311,12 +553,15 @@ impl InsertLength {
         *e += 1
     }
 
-    fn output(&self, cfg: &Config) -> anyhow::Result<()> {
-        let n = self.hash.values().sum::<u64>();
+    fn n(&self) -> u64 {
+        self.hash.values().sum::<u64>()
+    }
+
+    fn output(&self, out_name: &str) -> anyhow::Result<()> {
+        let n = self.n();
         if n > 0 {
-            let out_name = format!("{}_insert_len.tsv", cfg.output_prefix());
             let mut wrt = BufWriter::new(
-                File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
+                File::create(out_name).with_context(|| "Could not open output file {out_name}")?,
             );
             writeln!(wrt, "Length\tCount\t%")?;
             let n = n as f64;
@@ -329,6 +574,8 @@ impl InsertLength {
     }
 }
 
+/// Mutation co-occurrence counts for each pair of positions in an amplicon.  For an amplicon of
+/// length L this has L(L+1)/2 entries, and is the largest part of the amplicon statistics
 pub struct MutCorr<'a> {
     cts: Vec<[u64; 4]>,
     rf: &'a [u8],
@@ -394,8 +641,10 @@ impl<'a> MutCorr<'a> {
         assert_eq!(ct.next(), None);
     }
 
-    fn output(&self, cfg: &Config) -> anyhow::Result<Vec<[f64; 2]>> {
-        let out_name = format!("{}_mut_corr.tsv", cfg.output_prefix());
+    /// Output correlations between positions in `target`.  Returns the correlations for all
+    /// positions
+    fn output(&self, prefix: &str, target: &Range<usize>) -> anyhow::Result<Vec<[f64; 2]>> {
+        let out_name = format!("{prefix}_mut_corr.tsv");
         let mut wrt = BufWriter::new(
             File::create(&out_name).with_context(|| "Could not open output file {out_name}")?,
         );
@@ -403,8 +652,8 @@ impl<'a> MutCorr<'a> {
         let l = self.rf.len();
 
         let mut cm = vec![[0.0; 2]; l * l];
-        for i in 1..=l {
-            write!(wrt, "\t{i}")?;
+        for i in target.clone() {
+            write!(wrt, "\t{}", i + 1)?;
         }
         writeln!(wrt)?;
 
@@ -417,7 +666,9 @@ impl<'a> MutCorr<'a> {
         };
 
         for i in 0..l {
-            write!(wrt, "{}", i + 1)?;
+            if target.contains(&i) {
+                write!(wrt, "{}", i + 1)?;
+            }
             for j in 0..l {
                 let cts: &[u64; 4] = &self.cts[get_k(i, j)];
                 let n = (cts[0] + cts[1] + cts[2] + cts[3]) as f64;
@@ -437,9 +688,13 @@ impl<'a> MutCorr<'a> {
                     cm[j * l + i] = [z, r];
                     r2
                 };
-                write!(wrt, "\t{z:6.4}")?;
+                if target.contains(&i) && target.contains(&j) {
+                    write!(wrt, "\t{z:6.4}")?;
+                }
+            }
+            if target.contains(&i) {
+                writeln!(wrt)?;
             }
-            writeln!(wrt)?;
         }
         Ok(cm)
     }
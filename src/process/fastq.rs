@@ -1,6 +1,12 @@
+mod fastq_error;
 mod fastq_iter;
 mod fastq_record;
 mod lines;
+mod record_index;
+mod scan;
 
+pub use fastq_error::FastQErrorKind;
 pub use fastq_iter::FastQIter;
 pub use fastq_record::FastQRecord;
+pub use record_index::RecordIndex;
+pub use scan::{record_span, trim_cr};
@@ -0,0 +1,92 @@
+use std::{fmt, path::Path};
+
+/// Type of problem found when parsing a FASTQ record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastQErrorKind {
+    MissingAt,
+    MissingPlus,
+    EmptySequence,
+    LengthMismatch,
+    Incomplete,
+}
+
+impl FastQErrorKind {
+    pub const ALL: [Self; 5] = [
+        Self::MissingAt,
+        Self::MissingPlus,
+        Self::EmptySequence,
+        Self::LengthMismatch,
+        Self::Incomplete,
+    ];
+
+    /// Index of kind in `ALL`
+    pub fn ix(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for FastQErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::MissingAt => "header line does not start with '@'",
+            Self::MissingPlus => "separator line does not start with '+'",
+            Self::EmptySequence => "empty sequence",
+            Self::LengthMismatch => "sequence and quality lines have different lengths",
+            Self::Incomplete => "incomplete record",
+        };
+        f.write_str(s)
+    }
+}
+
+/// FASTQ parsing error with the location of the faulty record
+#[derive(Debug)]
+pub struct FastQError {
+    kind: FastQErrorKind,
+    file: Option<String>,
+    record: u64,
+    line: Option<u64>,
+    offset: Option<u64>,
+}
+
+impl FastQError {
+    /// `record` is the index of the record in the file and `line` the index of the line where
+    /// the problem was found (both counting from 0).  `offset` is the byte offset of the
+    /// start of the record in the file.  Line and offset are not known for all inputs
+    pub(super) fn new(
+        kind: FastQErrorKind,
+        file: Option<&Path>,
+        record: u64,
+        line: Option<u64>,
+        offset: Option<u64>,
+    ) -> Self {
+        Self {
+            kind,
+            file: file.map(|p| p.display().to_string()),
+            record,
+            line,
+            offset,
+        }
+    }
+
+    pub fn kind(&self) -> FastQErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for FastQError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = self.file.as_deref() {
+            write!(f, "Input file {file}: ")?
+        }
+        write!(f, "record {}", self.record + 1)?;
+        if let Some(l) = self.line {
+            write!(f, ", line {}", l + 1)?
+        }
+        if let Some(o) = self.offset {
+            write!(f, " (record starts at byte offset {o})")?
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for FastQError {}
@@ -1,45 +1,218 @@
-use super::{fastq_record::FastQRecord, lines::Lines};
+use std::{borrow::Cow, path::Path};
+
+use super::{
+    fastq_error::{FastQError, FastQErrorKind},
+    fastq_record::FastQRecord,
+    lines::Lines,
+    record_index::RecordIndex,
+    scan::record_span,
+};
 
 pub struct FastQIter<'a> {
     inner: &'a [u8],
+    /// Full input and record index (if available), with the index of the next record
+    index: Option<(&'a [u8], &'a RecordIndex)>,
+    next_rec: usize,
+    file: Option<&'a Path>,
+    rec: u64,
+    rec_step: u64,
+    line: Option<u64>,
+    offset: Option<u64>,
 }
 
 impl<'a> FastQIter<'a> {
     pub fn make(s: &'a [u8]) -> Self {
-        Self { inner: s }
+        Self {
+            inner: s,
+            index: None,
+            next_rec: 0,
+            file: None,
+            rec: 0,
+            rec_step: 1,
+            line: Some(0),
+            offset: Some(0),
+        }
+    }
+
+    /// Iterate over the records in `s` using a pre-built index
+    pub fn make_indexed(s: &'a [u8], index: &'a RecordIndex) -> Self {
+        Self {
+            index: Some((s, index)),
+            ..Self::make(s)
+        }
+    }
+
+    /// Set location of the start of the input slice for error reporting.  `rec` is the index
+    /// of the first record in the file, and successive records are `rec_step` apart in the file.
+    /// `line` and `offset` are the line index and byte offset of the start of the slice in the
+    /// file, if the slice is contiguous in the file
+    pub fn with_origin(
+        mut self,
+        file: &'a Path,
+        rec: u64,
+        rec_step: u64,
+        line: Option<u64>,
+        offset: Option<u64>,
+    ) -> Self {
+        self.file = Some(file);
+        self.rec = rec;
+        self.rec_step = rec_step;
+        self.line = line;
+        self.offset = offset;
+        self
+    }
+
+    /// Error for the current record, detected at line `line` of the record
+    fn error(&self, kind: FastQErrorKind, line: u64) -> FastQError {
+        FastQError::new(
+            kind,
+            self.file,
+            self.rec,
+            self.line.map(|l| l + line),
+            self.offset,
+        )
+    }
+
+    /// Skip past the current record, with length `len` bytes and `n_lines` lines
+    fn advance(&mut self, len: usize, n_lines: usize) {
+        self.offset = self.offset.map(|o| o + len as u64);
+        self.line = self.line.map(|l| l + n_lines as u64);
+        self.rec += self.rec_step;
+        self.next_rec += 1;
+        self.inner = &self.inner[len..];
+    }
+
+    /// Length in bytes and number of lines of the record at the start of the input
+    fn span(&self) -> Option<(usize, usize)> {
+        match self.index {
+            Some((_, ix)) if self.next_rec < ix.n_records() => {
+                let (p1, l1) = ix.rec_start(self.next_rec);
+                let (p2, l2) = ix.rec_start(self.next_rec + 1);
+                Some((p2 - p1, l2 - l1))
+            }
+            _ => record_span(self.inner, true).map(|r| (r.len, r.lines)),
+        }
+    }
+
+    /// Parse the record at the start of the input, with length `len` bytes
+    fn parse(&self, len: usize) -> Result<FastQRecord<'a>, FastQError> {
+        match self.index {
+            Some((s, ix)) if self.next_rec < ix.n_records() => {
+                self.parse_lines(ix.lines(s, self.next_rec))
+            }
+            _ => self.parse_lines(Lines::make(&self.inner[..len])),
+        }
+    }
+
+    /// Parse record from its lines
+    fn parse_lines<I>(&self, mut itr: I) -> Result<FastQRecord<'a>, FastQError>
+    where
+        I: Iterator<Item = &'a [u8]>,
+    {
+        let raw_id = itr.next().unwrap_or_default();
+        if raw_id.first() != Some(&b'@') {
+            return Err(self.error(FastQErrorKind::MissingAt, 0));
+        }
+
+        // Sequence continues until the '+' separator line
+        let mut seq = Cow::Borrowed(&[] as &[u8]);
+        let mut sep = None;
+        for (i, l) in itr.by_ref().enumerate() {
+            if i > 0 && l.first() == Some(&b'+') {
+                sep = Some(i as u64 + 1);
+                break;
+            }
+            append(&mut seq, l)
+        }
+        if seq.is_empty() {
+            return Err(self.error(FastQErrorKind::EmptySequence, 1));
+        }
+        let Some(sep) = sep else {
+            return Err(self.error(FastQErrorKind::MissingPlus, 2));
+        };
+
+        let mut qual = Cow::Borrowed(&[] as &[u8]);
+        for l in itr {
+            append(&mut qual, l)
+        }
+        if seq.len() != qual.len() {
+            return Err(self.error(FastQErrorKind::LengthMismatch, sep + 1));
+        }
+
+        Ok(FastQRecord::make(&raw_id[1..], seq, qual))
     }
 }
+
 impl<'a> Iterator for FastQIter<'a> {
-    type Item = anyhow::Result<FastQRecord<'a>>;
+    type Item = Result<FastQRecord<'a>, FastQError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.inner.is_empty() {
             None
         } else {
-            Some({
-                let mut itr = Lines::make(self.inner);
-                let raw_id = itr.next();
-                let seq = itr.next();
-                let id2 = itr.next();
-                let qual = itr.next();
-                if let (Some(raw_id), Some(seq), Some(id2), Some(qual)) = (raw_id, seq, id2, qual) {
-                    if raw_id.is_empty()
-                        || raw_id[0] != b'@'
-                        || id2.is_empty()
-                        || id2[0] != b'+'
-                        || seq.is_empty()
-                        || seq.len() != qual.len()
-                    {
-                        Err(anyhow!("Invalid FASTQ record"))
-                    } else {
-                        let id = if raw_id.len() == 1 { &[] } else { &raw_id[1..] };
-                        self.inner = itr.inner();
-                        Ok(FastQRecord::make(id, seq, qual))
-                    }
-                } else {
-                    Err(anyhow!("Incomplete FASTQ record"))
+            // After an error, the iterator moves to the next record so that the caller
+            // can skip invalid records
+            Some(match self.span() {
+                Some((len, n_lines)) => {
+                    let r = self.parse(len);
+                    self.advance(len, n_lines);
+                    r
+                }
+                None => {
+                    let e = self.error(FastQErrorKind::Incomplete, 0);
+                    self.inner = &[];
+                    Err(e)
                 }
             })
         }
     }
 }
+
+/// Append line to (possibly) borrowed data, only copying if there is more than one line
+fn append<'a>(s: &mut Cow<'a, [u8]>, l: &'a [u8]) {
+    if s.is_empty() {
+        *s = Cow::Borrowed(l)
+    } else {
+        s.to_mut().extend_from_slice(l)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"@r0\nACGT\n+\nIIII\n@r1\nACGT\n+\nIII\n@r2\nACGT\n+\nIIII\n";
+
+    fn errors(itr: FastQIter) -> Vec<String> {
+        itr.filter_map(|r| r.err()).map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn error_position() {
+        let expected = vec![
+            "Input file in.fq: record 12, line 108 (record starts at byte offset 5016): \
+             sequence and quality lines have different lengths"
+                .to_owned(),
+        ];
+        let f = Path::new("in.fq");
+        let itr = FastQIter::make(DATA).with_origin(f, 10, 1, Some(100), Some(5000));
+        assert_eq!(errors(itr), expected);
+
+        let mut ix = RecordIndex::default();
+        ix.build(DATA, true);
+        let itr = FastQIter::make_indexed(DATA, &ix).with_origin(f, 10, 1, Some(100), Some(5000));
+        assert_eq!(errors(itr), expected);
+    }
+
+    #[test]
+    fn interleaved_error_position() {
+        // Records from an interleaved file are two records apart, and lines and offsets
+        // are not known
+        let f = Path::new("in.fq");
+        let itr = FastQIter::make(&DATA[16..]).with_origin(f, 5, 2, None, None);
+        assert_eq!(
+            errors(itr),
+            vec!["Input file in.fq: record 6: sequence and quality lines have different lengths"]
+        );
+    }
+}
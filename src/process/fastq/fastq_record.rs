@@ -1,8 +1,12 @@
-#[derive(Copy, Clone)]
+use std::borrow::Cow;
+
+/// FASTQ record.  The sequence and quality are borrowed from the input unless they are split
+/// over multiple lines
+#[derive(Clone)]
 pub struct FastQRecord<'a> {
     id: &'a [u8],
-    seq: &'a [u8],
-    qual: &'a [u8],
+    seq: Cow<'a, [u8]>,
+    qual: Cow<'a, [u8]>,
 }
 
 impl<'a> FastQRecord<'a> {
@@ -14,15 +18,28 @@ impl<'a> FastQRecord<'a> {
 
     #[inline]
     pub fn qual(&self) -> &[u8] {
-        self.qual
+        &self.qual
     }
 
     #[inline]
     pub fn seq(&self) -> &[u8] {
-        self.seq
+        &self.seq
+    }
+
+    /// Shorten the record to (at most) the first `n` bases
+    pub fn truncate(&mut self, n: usize) {
+        for s in [&mut self.seq, &mut self.qual] {
+            match s {
+                Cow::Borrowed(x) => {
+                    let y: &'a [u8] = x;
+                    *x = &y[..n.min(y.len())]
+                }
+                Cow::Owned(v) => v.truncate(n),
+            }
+        }
     }
 
-    pub(super) fn make(id: &'a [u8], seq: &'a [u8], qual: &'a [u8]) -> Self {
+    pub(super) fn make(id: &'a [u8], seq: Cow<'a, [u8]>, qual: Cow<'a, [u8]>) -> Self {
         Self { id, seq, qual }
     }
 }
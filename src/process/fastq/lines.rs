@@ -1,3 +1,6 @@
+use super::scan::trim_cr;
+
+/// Iterator over lines, removing `\n` or `\r\n` line terminators
 pub struct Lines<'a> {
     inner: &'a [u8],
 }
@@ -6,10 +9,6 @@ impl <'a> Lines<'a> {
     pub(super) fn make(s: &'a [u8]) -> Self {
         Self { inner: s }
     }
-    
-    pub(super) fn inner(&self) -> &'a [u8] {
-        self.inner
-    }
 }
 
 impl<'a> Iterator for Lines<'a> {
@@ -18,15 +17,14 @@ impl<'a> Iterator for Lines<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.inner.is_empty() {
             None
-        } else if let Some((i, _)) = self.inner.iter().enumerate().find(|(_, c)| **c == b'\n') {
+        } else if let Some(i) = memchr::memchr(b'\n', self.inner) {
             let (s1, s2) = self.inner.split_at(i);
             self.inner = &s2[1..];
-            Some(s1)
+            Some(trim_cr(s1))
         } else {
             let s = self.inner;
             self.inner = &[];
-            Some(s)
+            Some(trim_cr(s))
         }
     }
 }
-
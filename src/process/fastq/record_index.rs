@@ -0,0 +1,133 @@
+use super::scan::{record_lines, trim_cr};
+
+/// Positions of line ends and record starts for a block of FASTQ records, built in a single
+/// pass over the data so that records can be parsed without searching for newlines again
+#[derive(Debug, Default)]
+pub struct RecordIndex {
+    /// Offsets of newlines
+    line_ends: Vec<usize>,
+    /// Index of the first line of each record, followed by the index after the last record
+    rec_starts: Vec<usize>,
+    /// Length of the indexed data
+    len: usize,
+}
+
+impl RecordIndex {
+    /// Index the complete records in `s`.  If `eof` is set, the last line of `s` does not
+    /// need to be terminated by a newline
+    pub fn build(&mut self, s: &[u8], eof: bool) {
+        self.len = s.len();
+        self.line_ends.clear();
+        self.line_ends.extend(memchr::memchr_iter(b'\n', s));
+        if eof && line_start(&self.line_ends, self.line_ends.len(), s.len()) < s.len() {
+            self.line_ends.push(s.len())
+        }
+        self.rec_starts.clear();
+        self.rec_starts.push(0);
+        let line_ends = &self.line_ends;
+        let mut k = 0;
+        while let Some(n) = record_lines(|j| get_line(s, line_ends, k + j), false) {
+            k += n;
+            self.rec_starts.push(k)
+        }
+    }
+
+    /// Number of complete records
+    pub fn n_records(&self) -> usize {
+        self.rec_starts.len().saturating_sub(1)
+    }
+
+    /// Byte offset and line index of the start of record `i`.  If `i` is greater than or
+    /// equal to the number of records, gives the position after the last complete record
+    pub fn rec_start(&self, i: usize) -> (usize, usize) {
+        let k = self.end_line(i);
+        (line_start(&self.line_ends, k, self.len), k)
+    }
+
+    /// Keep the first `n` records (or all records if there are fewer than `n`)
+    pub fn truncate(&mut self, n: usize) {
+        let k = self.end_line(n);
+        self.rec_starts.truncate(n + 1);
+        self.line_ends.truncate(k);
+    }
+
+    /// Index of the first line of record `i`, or of the line after the last complete record
+    /// if `i` is out of range
+    fn end_line(&self, i: usize) -> usize {
+        self.rec_starts
+            .get(i)
+            .or(self.rec_starts.last())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.line_ends.clear();
+        self.rec_starts.clear();
+        self.len = 0;
+    }
+
+    /// Lines of record `i` from `s`, the data that was indexed
+    pub(super) fn lines<'a>(&'a self, s: &'a [u8], i: usize) -> impl Iterator<Item = &'a [u8]> {
+        (self.rec_starts[i]..self.rec_starts[i + 1])
+            .map(move |k| get_line(s, &self.line_ends, k).expect("Corrupt record index"))
+    }
+}
+
+/// Offset of the start of line `k` in data of length `len` (the last line end can be at `len`
+/// if the data does not finish with a newline)
+#[inline]
+fn line_start(line_ends: &[usize], k: usize, len: usize) -> usize {
+    if k == 0 {
+        0
+    } else {
+        (line_ends[k - 1] + 1).min(len)
+    }
+}
+
+/// Line `k` without the line terminator
+#[inline]
+fn get_line<'a>(s: &'a [u8], line_ends: &[usize], k: usize) -> Option<&'a [u8]> {
+    let end = *line_ends.get(k)?;
+    Some(trim_cr(&s[line_start(line_ends, k, s.len())..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"@r1\nACGT\n+\nIIII\n@r2\nAC\r\n+\nII\n@r3\nAC\n+\nII";
+
+    #[test]
+    fn index_records() {
+        let mut ix = RecordIndex::default();
+        ix.build(DATA, false);
+        // Last record is not complete without a final newline unless at EOF
+        assert_eq!(ix.n_records(), 2);
+        assert_eq!(ix.rec_start(0), (0, 0));
+        assert_eq!(ix.rec_start(1), (16, 4));
+        assert_eq!(ix.rec_start(2), (29, 8));
+        assert_eq!(ix.rec_start(5), (29, 8));
+        let lines: Vec<_> = ix.lines(DATA, 1).collect();
+        assert_eq!(lines, [&b"@r2"[..], b"AC", b"+", b"II"]);
+
+        ix.build(DATA, true);
+        assert_eq!(ix.n_records(), 3);
+        assert_eq!(ix.rec_start(3), (DATA.len(), 12));
+        assert_eq!(ix.lines(DATA, 2).last(), Some(&b"II"[..]));
+    }
+
+    #[test]
+    fn truncate_records() {
+        let mut ix = RecordIndex::default();
+        ix.build(DATA, true);
+        ix.truncate(1);
+        assert_eq!(ix.n_records(), 1);
+        assert_eq!(ix.rec_start(1), (16, 4));
+        ix.truncate(5);
+        assert_eq!(ix.n_records(), 1);
+        ix.clear();
+        assert_eq!(ix.n_records(), 0);
+        assert_eq!(ix.rec_start(0), (0, 0));
+    }
+}
@@ -0,0 +1,170 @@
+/// Extent of a FASTQ record
+#[derive(Debug, Clone, Copy)]
+pub struct RecordSpan {
+    /// Length in bytes, including the trailing newline (if present)
+    pub len: usize,
+    /// Number of lines
+    pub lines: usize,
+}
+
+/// Find the extent of the first FASTQ record in `s` (see [record_lines]).
+///
+/// Returns None if `s` does not contain a complete record.  If `eof` is set, the last line of
+/// `s` does not need to be terminated by a newline
+pub fn record_span(s: &[u8], eof: bool) -> Option<RecordSpan> {
+    // Lines are normally requested in order, but we restart from the beginning of the record if
+    // an earlier line is needed
+    let (mut pos, mut next) = (0, 0);
+    let lines = record_lines(
+        |k| {
+            if k < next {
+                (pos, next) = (0, 0)
+            }
+            let mut l = None;
+            while next <= k {
+                let (x, q) = next_line(s, pos, eof)?;
+                (pos, next) = (q, next + 1);
+                l = Some(x)
+            }
+            l
+        },
+        eof,
+    )?;
+    // The line after the record may have been examined
+    if next > lines {
+        pos = (0..lines).try_fold(0, |p, _| next_line(s, p, eof).map(|(_, q)| q))?
+    }
+    Some(RecordSpan { len: pos, lines })
+}
+
+/// Find the number of lines in a FASTQ record, where `line(k)` returns line `k` of the record
+/// without the line terminator, or None if the line is not available.
+///
+/// Records can have the sequence and quality split over multiple lines, and lines can be
+/// terminated with `\r\n`.  The record ends when the number of quality values reaches the
+/// number of bases, or before a line that can not continue the quality (see below).
+/// Malformed records (with an invalid header, or where the '+' separator is missing) are
+/// assumed to be 4 lines long, so that the error can be reported by the parser.
+///
+/// Returns None if the record is incomplete.  If `eof` is set, a record with too few quality
+/// values at the end of the input is taken as complete
+pub fn record_lines<'a, F>(mut line: F, eof: bool) -> Option<usize>
+where
+    F: FnMut(usize) -> Option<&'a [u8]>,
+{
+    let hdr = line(0)?;
+    let mut seq_len = line(1)?.len();
+    let mut k = 2;
+
+    if hdr.first() != Some(&b'@') {
+        return line(3).map(|_| 4);
+    }
+    // Sequence continues until the '+' line
+    loop {
+        let l = line(k)?;
+        k += 1;
+        match l.first() {
+            Some(b'+') => break,
+            // Start of the next record, so the separator is missing
+            Some(b'@') => return line(3).map(|_| 4),
+            _ => seq_len += l.len(),
+        }
+    }
+    let n_seq_lines = k - 2;
+    // Quality continues until we have one value per base
+    let mut l = line(k)?;
+    k += 1;
+    let (mut qual_len, mut n_qual_lines) = (l.len(), 1);
+    while qual_len < seq_len && !l.is_empty() {
+        // At EOF, a short quality line ends the record
+        let Some(next) = line(k) else {
+            return eof.then_some(k);
+        };
+        // A line that would give too many quality values is not part of the record.  Quality
+        // lines can start with '@', so a line starting with '@' is only taken as quality if the
+        // quality does not already have as many lines as the sequence.  Otherwise it is taken as
+        // the start of the next record, and the short quality is reported by the parser
+        if qual_len + next.len() > seq_len
+            || (next.first() == Some(&b'@') && n_qual_lines >= n_seq_lines)
+        {
+            break;
+        }
+        l = next;
+        k += 1;
+        n_qual_lines += 1;
+        qual_len += l.len();
+    }
+    Some(k)
+}
+
+/// Line starting at `pos` without the line terminator, and the start of the next line
+fn next_line(s: &[u8], pos: usize, eof: bool) -> Option<(&[u8], usize)> {
+    if pos >= s.len() {
+        return None;
+    }
+    match memchr::memchr(b'\n', &s[pos..]) {
+        Some(i) => Some((trim_cr(&s[pos..pos + i]), pos + i + 1)),
+        None if eof => Some((trim_cr(&s[pos..]), s.len())),
+        None => None,
+    }
+}
+
+/// Remove trailing carriage return
+#[inline]
+pub fn trim_cr(s: &[u8]) -> &[u8] {
+    s.strip_suffix(b"\r").unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &[u8], eof: bool) -> Option<usize> {
+        record_span(s, eof).map(|r| r.lines)
+    }
+
+    #[test]
+    fn multi_line_record() {
+        let s = b"@r1\nACGT\nAC\n+\nIIII\nII\n@r2\nA\n+\nI\n";
+        let r = record_span(s, false).unwrap();
+        assert_eq!((r.len, r.lines), (22, 6));
+        assert_eq!(lines(&s[r.len..], false), Some(4));
+    }
+
+    #[test]
+    fn crlf_record() {
+        let s = b"@r1\r\nACGT\r\n+\r\nIIII\r\n@r2\r\n";
+        assert_eq!(
+            record_span(s, false).map(|r| (r.len, r.lines)),
+            Some((20, 4))
+        );
+    }
+
+    #[test]
+    fn short_quality() {
+        // The next header is not taken as quality, even though it would fit
+        let s = b"@r1\nACGTAC\n+\nIII\n@r2\nACG\n+\nIII\n";
+        let r = record_span(s, false).unwrap();
+        assert_eq!((r.len, r.lines), (17, 4));
+        assert_eq!(lines(&s[r.len..], false), Some(4));
+
+        // A line that would give too many quality values is not taken
+        assert_eq!(lines(b"@r1\nACGT\nAC\n+\nII\nIIIII\n", false), Some(5));
+
+        // At EOF a short quality ends the record, otherwise we wait for more input
+        assert_eq!(lines(b"@r1\nACGT\n+\nII", true), Some(4));
+        assert_eq!(lines(b"@r1\nACGT\n+\nII\n", false), None);
+    }
+
+    #[test]
+    fn quality_starting_with_at() {
+        let s = b"@r1\nACGT\n+\n@III\n@r2\nACGT\n+\nIIII\n";
+        assert_eq!(lines(s, false), Some(4));
+
+        // Continuation quality line starting with '@' in a multi-line record
+        let s = b"@r1\nACGT\nACGT\n+\nIIII\n@III\n@r2\nA\n+\nI\n";
+        let r = record_span(s, false).unwrap();
+        assert_eq!((r.len, r.lines), (26, 6));
+        assert_eq!(lines(&s[r.len..], false), Some(4));
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use crate::cli::Amplicon;
+
+/// K-mer size used to determine read orientation
+const K: usize = 12;
+
+/// Determines the orientation of reads relative to the amplicons by counting the k-mers that
+/// the read shares with the amplicon sequences in each orientation
+pub struct Orienter {
+    kmers: HashSet<u32>,
+}
+
+impl Orienter {
+    pub fn new(amplicons: &[Amplicon]) -> Self {
+        let mut kmers = HashSet::new();
+        for a in amplicons {
+            for_each_kmer(a.seq(), |f, _| {
+                kmers.insert(f);
+            })
+        }
+        Self { kmers }
+    }
+
+    /// Returns true if more k-mers from the reverse complement of `s` are found in the amplicons
+    /// than from `s` itself, or None if no k-mers are found in either orientation
+    pub fn is_reversed(&self, s: &[u8]) -> Option<bool> {
+        let (mut fwd, mut rev) = (0, 0);
+        for_each_kmer(s, |f, r| {
+            fwd += usize::from(self.kmers.contains(&f));
+            rev += usize::from(self.kmers.contains(&r));
+        });
+        (fwd + rev > 0).then_some(rev > fwd)
+    }
+}
+
+/// Call `f` with each k-mer in `s` and its reverse complement (2 bits per base).  K-mers
+/// containing bases other than A, C, G or T are skipped
+fn for_each_kmer<F: FnMut(u32, u32)>(s: &[u8], mut f: F) {
+    let mask = (1u32 << (K << 1)) - 1;
+    let (mut fwd, mut rev, mut l) = (0u32, 0u32, 0);
+    for c in s {
+        let x = match c {
+            b'A' | b'a' => 0,
+            b'C' | b'c' => 1,
+            b'G' | b'g' => 2,
+            b'T' | b't' => 3,
+            _ => {
+                l = 0;
+                continue;
+            }
+        };
+        fwd = ((fwd << 2) | x) & mask;
+        rev = (rev >> 2) | ((3 - x) << ((K - 1) << 1));
+        l += 1;
+        if l >= K {
+            f(fwd, rev)
+        }
+    }
+}
@@ -1,15 +1,21 @@
-use std::thread;
+use std::{
+    sync::{Mutex, atomic::AtomicU64},
+    thread,
+};
 
 use crossbeam_channel::{Receiver, Sender};
 
 use super::{
     Buffer,
-    counts::Stats,
-    view::ViewBuf,
+    counts::SampleStats,
+    orient::Orienter,
+    view::{SampleProgress, ViewMsg},
 };
 
 use crate::cli::Config;
 
+mod adapter;
+mod primer;
 mod process_thread;
 mod process_buffer;
 use process_thread::process_thread;
@@ -18,11 +24,16 @@ pub fn process_threads<'a> (
     cfg: &'a Config,
     rcv: Receiver<Buffer>,
     snd: Sender<Buffer>,
-    snd_view: Option<Sender<ViewBuf>>,
-) -> anyhow::Result<Stats<'a>> {
+    snd_view: Option<(Sender<ViewMsg>, &SampleProgress)>,
+) -> anyhow::Result<Vec<SampleStats<'a>>> {
     let nt = cfg.threads();
     let mut error = None;
-    let mut stats = Stats::new(cfg.reference());
+    // Statistics for all samples, shared between the process threads
+    let stats = Mutex::new((0..cfg.n_samples()).map(|_| SampleStats::new(cfg)).collect());
+    // Number of invalid records skipped (over all threads)
+    let n_bad = AtomicU64::new(0);
+    // Long reads can be in either orientation
+    let orienter = cfg.long_reads().then(|| Orienter::new(cfg.amplicons()));
     
     thread::scope(|scope| {
         debug!("Setting up process thread(s)");
@@ -32,7 +43,12 @@ pub fn process_threads<'a> (
                 let recv_buf = rcv.clone();
                 let send_buf = snd.clone();
                 let send_view = snd_view.as_ref().cloned();
-                scope.spawn(move || process_thread(cfg, ix, recv_buf, send_buf, send_view))
+                let n_bad = &n_bad;
+                let orienter = orienter.as_ref();
+                let stats = &stats;
+                scope.spawn(move || {
+                    process_thread(cfg, ix, recv_buf, send_buf, send_view, n_bad, orienter, stats)
+                })
             })
             .collect();
 
@@ -40,18 +56,18 @@ pub fn process_threads<'a> (
 
         // Wait for process threads to finish
         for jh in process_handles {
-            match jh.join().expect("Error joining process threads") {
-                Err(e) => {
-                    if error.is_none() {
-                        error = Some(e)
-                    }
-                }
-                Ok(s) => stats += s,
+            if let Err(e) = jh.join().expect("Error joining process threads")
+                && error.is_none()
+            {
+                error = Some(e)
             }
         }
     });
 
     debug!("Process thread(s) finished");
 
-    if let Some(e) = error { Err(e) } else { Ok(stats) }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(stats.into_inner().expect("Error locking sample statistics")),
+    }
 }
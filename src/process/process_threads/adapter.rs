@@ -0,0 +1,175 @@
+use memchr::memmem;
+
+use super::process_buffer::complement;
+
+use crate::{
+    cli::{Config, iupac_compatible},
+    process::fastq::FastQRecord,
+};
+
+/// Minimum overlap between the end of a read and a supplied adapter sequence
+const MIN_ADAPTER_OVERLAP: usize = 5;
+
+/// Minimum insert length that can be detected from the overlap of a read pair
+const MIN_INSERT: usize = 16;
+
+/// Length of the seed (exact match) used to find candidate overlaps of a read pair
+const SEED_LEN: usize = 12;
+
+/// Reads (or read pairs) shorter than this after adapter trimming (e.g., adapter dimers)
+/// are rejected
+pub(super) const MIN_TRIMMED_LEN: usize = 16;
+
+/// Trim adapter read through from a read pair.  Adapters are located using the supplied
+/// sequences if available, otherwise from the overlap between the reads.  Returns true if
+/// either read was trimmed
+pub(super) fn trim_pair(cfg: &Config, r1: &mut FastQRecord, r2: &mut FastQRecord) -> bool {
+    let (x1, x2) = match cfg.adapters() {
+        Some([a1, a2]) => (find_adapter(r1.seq(), a1), find_adapter(r2.seq(), a2)),
+        None => {
+            let n = insert_len(r1.seq(), r2.seq());
+            (n, n)
+        }
+    };
+    if let Some(n) = x1 {
+        r1.truncate(n)
+    }
+    if let Some(n) = x2 {
+        r2.truncate(n)
+    }
+    x1.is_some() || x2.is_some()
+}
+
+/// Trim adapter from a single read.  This requires a supplied adapter sequence.  Returns true
+/// if the read was trimmed
+pub(super) fn trim_single(cfg: &Config, r: &mut FastQRecord) -> bool {
+    match cfg.adapters().and_then(|[a, _]| find_adapter(r.seq(), a)) {
+        Some(n) => {
+            r.truncate(n);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Position of the start of `adapter` in `read`.  The adapter can extend past the end of the
+/// read provided that at least [MIN_ADAPTER_OVERLAP] bases (or the whole adapter) overlap
+fn find_adapter(read: &[u8], adapter: &[u8]) -> Option<usize> {
+    let l = read.len();
+    if l < MIN_ADAPTER_OVERLAP {
+        return None;
+    }
+    (0..=l - MIN_ADAPTER_OVERLAP).find(|&i| {
+        let n = (l - i).min(adapter.len());
+        similar(
+            read[i..i + n].iter().copied(),
+            adapter[..n].iter().copied(),
+            n,
+        )
+    })
+}
+
+/// Insert length if the reads of a pair run through into adapter.  This is detected when the
+/// start of read 1 matches the reverse complement of the start of read 2 for a length less than
+/// the length of both reads.  The longest such match is returned.
+///
+/// Only candidate lengths where the reverse complement of the first [SEED_LEN] bases of one read
+/// is found exactly in the other read are checked
+fn insert_len(r1: &[u8], r2: &[u8]) -> Option<usize> {
+    let l = r1.len().min(r2.len());
+    if l <= MIN_INSERT {
+        return None;
+    }
+    let seed = |r: &[u8]| {
+        let mut s = [0; SEED_LEN];
+        for (x, c) in s.iter_mut().zip(r[..SEED_LEN].iter().rev()) {
+            *x = complement(c)
+        }
+        s
+    };
+    let (s1, s2) = (seed(r2), seed(r1));
+    memmem::find_iter(&r1[..l - 1], &s1)
+        .chain(memmem::find_iter(&r2[..l - 1], &s2))
+        .map(|i| i + SEED_LEN)
+        .filter(|&n| {
+            n >= MIN_INSERT
+                && similar(
+                    r1[..n].iter().copied(),
+                    r2[..n].iter().rev().map(complement),
+                    n,
+                )
+        })
+        .max()
+}
+
+/// Check whether two sequences of length `n` differ at no more than 10% of positions.
+/// Sequences can contain IUPAC codes: positions where the codes can match the same base
+/// (including any position with an N) are not counted as differences
+pub(super) fn similar<I, J>(a: I, b: J, n: usize) -> bool
+where
+    I: Iterator<Item = u8>,
+    J: Iterator<Item = u8>,
+{
+    let max_mm = n / 10;
+    let mut mm = 0;
+    for (x, y) in a.zip(b) {
+        if !iupac_compatible(x, y) {
+            mm += 1;
+            if mm > max_mm {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revcomp(s: &[u8]) -> Vec<u8> {
+        s.iter().rev().map(complement).collect()
+    }
+
+    /// Read pair with adapter read through
+    fn read_pair(insert: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut r1 = insert.to_vec();
+        r1.extend_from_slice(b"AGATCGGAAGAGCACACGTC");
+        let mut r2 = revcomp(insert);
+        r2.extend_from_slice(b"AGATCGGAAGAGCGTCGTGT");
+        (r1, r2)
+    }
+
+    #[test]
+    fn adapter_position() {
+        let adapter = b"AGATCGGAAGAGC";
+        assert_eq!(
+            find_adapter(b"ACGTACGTTTGCAGATCGGAAGAGCAC", adapter),
+            Some(12)
+        );
+        // Adapter partly past the end of the read
+        assert_eq!(find_adapter(b"ACGTACGTTTGCAAGATCGG", adapter), Some(13));
+        assert_eq!(find_adapter(b"ACGTACGTTTGCAAGAT", adapter), None);
+        // Adapter dimer
+        assert_eq!(find_adapter(b"AGATCGGAAGAGCACACGTCTGAAC", adapter), Some(0));
+    }
+
+    #[test]
+    fn insert_from_overlap() {
+        let insert = b"TTGACCAGTAGGCATCATGGGCCTTTTTTTGGCCTTATGG";
+        let (r1, mut r2) = read_pair(insert);
+        assert_eq!(insert_len(&r1, &r2), Some(insert.len()));
+
+        // With a mismatch in the seed of read 2
+        r2[3] = if r2[3] == b'A' { b'C' } else { b'A' };
+        assert_eq!(insert_len(&r1, &r2), Some(insert.len()));
+
+        // No read through
+        let r2 = revcomp(&r1);
+        assert_eq!(insert_len(&r1, &r2), None);
+
+        // Insert too short
+        let (r1, r2) = read_pair(&insert[..10]);
+        assert_eq!(insert_len(&r1, &r2), None);
+    }
+}
@@ -0,0 +1,78 @@
+use super::adapter::similar;
+
+use crate::cli::Config;
+
+/// Shifts (in order of preference) of the primer positions from the ends of the reads that are
+/// tried.  A positive shift means extra bases between the end of the read and the primer, and
+/// a negative shift that the outer bases of the primer are missing (e.g., from small indels in
+/// the primer or synthesis errors)
+const PRIMER_SHIFTS: [isize; 5] = [0, -1, 1, -2, 2];
+
+/// Check that `start` begins with the forward primer and `end` finishes with the (reverse
+/// complemented) reverse primer, allowing for a few mismatches and small shifts
+/// ([PRIMER_SHIFTS]).  Degenerate (IUPAC) primer bases match any of the bases they represent.
+/// Returns the number of bases to remove from the start and end (the primers plus any bases
+/// outside of them), or None if either primer is missing.  If no primers were supplied, the
+/// returned lengths are zero
+pub(super) fn find_primers(cfg: &Config, start: &[u8], end: &[u8]) -> Option<[usize; 2]> {
+    let Some([p1, p2]) = cfg.primers() else {
+        return Some([0, 0]);
+    };
+    Some([primer_len(start, p1, false)?, primer_len(end, p2, true)?])
+}
+
+/// Find primer `p` at the start (or end if `at_end` is set) of `s`.  Returns the number of
+/// bases of `s` up to the inner end of the primer
+fn primer_len(s: &[u8], p: &[u8], at_end: bool) -> Option<usize> {
+    let (ls, lp) = (s.len(), p.len());
+    PRIMER_SHIFTS.iter().find_map(|&d| {
+        let n = lp.checked_add_signed(d).filter(|n| *n > 0 && *n <= ls)?;
+        // Number of primer bases compared
+        let k = lp.min(n);
+        let (x, y) = if at_end {
+            (&s[ls - n..ls - n + k], &p[..k])
+        } else {
+            (&s[n - k..n], &p[lp - k..])
+        };
+        similar(x.iter().copied(), y.iter().copied(), k).then_some(n)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primer_at_read_ends() {
+        let p = b"ACGTRYACGTACGTNCCGTA";
+        let s = b"ACGTACACGTACGTGCCGTATTTTTTTTTT";
+        assert_eq!(primer_len(s, p, false), Some(20));
+        assert_eq!(primer_len(b"ACGTACGTACG", p, false), None);
+
+        let mut r = s.to_vec();
+        r.reverse();
+        let mut q = p.to_vec();
+        q.reverse();
+        assert_eq!(primer_len(&r, &q, true), Some(20));
+    }
+
+    #[test]
+    fn primer_degenerate_mismatch() {
+        // R (A or G) does not match C or T
+        let p = b"ACGTRRRACGTACGTACGTA";
+        assert_eq!(primer_len(b"ACGTCCTACGTACGTACGTATT", p, false), None);
+        assert_eq!(primer_len(b"ACGTAGAACGTACGTACGTATT", p, false), Some(20));
+    }
+
+    #[test]
+    fn shifted_primer() {
+        let p = b"ACGTTGCAACGTTGCAACGT";
+        // Extra base before the primer
+        assert_eq!(primer_len(b"GACGTTGCAACGTTGCAACGTCC", p, false), Some(21));
+        // First base of primer missing
+        assert_eq!(primer_len(b"CGTTGCAACGTTGCAACGTCC", p, false), Some(19));
+        // Extra bases after the primer at the end of the read
+        assert_eq!(primer_len(b"CCACGTTGCAACGTTGCAACGTAA", p, true), Some(22));
+        assert_eq!(primer_len(b"CCACGTTGCAACGTTGCAACG", p, true), Some(19));
+    }
+}
@@ -1,31 +1,105 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use anyhow::Context;
 
+use super::{
+    adapter::{MIN_TRIMMED_LEN, trim_pair, trim_single},
+    primer::find_primers,
+};
 use crate::process::Buffer;
 
 use crate::{
-    cli::Config,
-    process::{align::Aligner, counts::Stats, fastq::FastQRecord, view::ViewStore},
+    cli::{Config, InputMode},
+    process::{
+        align::Aligner,
+        counts::{SampleStats, Stats},
+        fastq::FastQRecord,
+        orient::Orienter,
+        read_id::{IdMatch, first_token, match_ids},
+        view::ViewStore,
+    },
 };
 
+/// Process records in buffer.  Invalid records are skipped and counted unless this would take
+/// the number of bad records for the run (`n_bad`) over the limit from the configuration
+#[allow(clippy::too_many_arguments)]
 pub(super) fn process_buffer<'a>(
     cfg: &'a Config,
     b: &Buffer,
+    merge_aligner: &mut Aligner,
     aligner: &mut Aligner,
-    stats: &mut Stats<'a>,
+    stats: &mut SampleStats<'a>,
     overlap_buf: &mut Vec<u8>,
     al_buf: &mut Vec<u8>,
     mut view_data: Option<&mut ViewStore>,
+    n_bad: &AtomicU64,
+    orienter: Option<&Orienter>,
 ) -> anyhow::Result<()> {
-    let (fq1, fq2) = b.fastq();
+    let (fq1, fq2) = b.fastq(cfg);
+    let sample = b.sample();
+    let qual_offset = b.qual_offset();
+    let max_bad = cfg.max_bad_records();
+
+    if cfg.input_mode() == InputMode::Single {
+        for r in fq1 {
+            let rec = match r {
+                Ok(r) => r,
+                Err(e) => {
+                    stats.add_bad_record(e.kind());
+                    skip_bad_record(max_bad, n_bad, e.into())?;
+                    continue;
+                }
+            };
+            process_single(
+                cfg,
+                sample,
+                qual_offset,
+                rec,
+                orienter,
+                stats,
+                aligner,
+                overlap_buf,
+                al_buf,
+                &mut view_data,
+            )?
+        }
+        return Ok(());
+    }
 
     for (r1, r2) in fq1.zip(fq2) {
-        let rec1 = r1?;
-        let rec2 = r2?;
+        let (rec1, rec2) = match (r1, r2) {
+            (Ok(r1), Ok(r2)) => (r1, r2),
+            (Err(e), _) | (_, Err(e)) => {
+                stats.add_bad_record(e.kind());
+                skip_bad_record(max_bad, n_bad, e.into())?;
+                continue;
+            }
+        };
+        match match_ids(cfg.pairing(), rec1.id(), rec2.id()) {
+            IdMatch::Exact => {}
+            IdMatch::Suffix => stats.add_id_suffix(),
+            IdMatch::Mismatch => {
+                stats.add_bad_id();
+                skip_bad_record(
+                    max_bad,
+                    n_bad,
+                    anyhow!(
+                        "Mismatch between IDs of read 1 and read 2 ({} / {})",
+                        String::from_utf8_lossy(first_token(rec1.id())),
+                        String::from_utf8_lossy(first_token(rec2.id()))
+                    ),
+                )?;
+                continue;
+            }
+        }
         process_records(
             cfg,
+            sample,
+            qual_offset,
             rec1,
             rec2,
             stats,
+            merge_aligner,
             aligner,
             overlap_buf,
             al_buf,
@@ -36,75 +110,144 @@ pub(super) fn process_buffer<'a>(
     Ok(())
 }
 
+/// Skip invalid record, returning the error if skipping is not allowed (`max` is zero) or if
+/// the limit `max` on the number of bad records is exceeded
+fn skip_bad_record(max: u64, n_bad: &AtomicU64, e: anyhow::Error) -> anyhow::Result<()> {
+    if max == 0 {
+        return Err(e);
+    }
+    let n = n_bad.fetch_add(1, Ordering::Relaxed) + 1;
+    if n > max {
+        Err(e.context(format!("Too many invalid records (limit {max})")))
+    } else {
+        debug!("Skipping invalid record: {e}");
+        Ok(())
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn process_records(
     cfg: &Config,
-    rec1: FastQRecord,
-    rec2: FastQRecord,
-    stats: &mut Stats,
+    sample: usize,
+    qual_offset: u8,
+    mut rec1: FastQRecord,
+    mut rec2: FastQRecord,
+    stats: &mut SampleStats,
+    merge_aligner: &mut Aligner,
     aligner: &mut Aligner,
     ov_buf: &mut Vec<u8>,
     al_buf: &mut Vec<u8>,
     view_data: &mut Option<&mut ViewStore>,
 ) -> anyhow::Result<()> {
-    let s1 = rec1
-        .id()
-        .split(|c| c.is_ascii_whitespace())
-        .next()
-        .with_context(|| "Missing read ID")?;
-    let s2 = rec2
-        .id()
-        .split(|c| c.is_ascii_whitespace())
-        .next()
-        .with_context(|| "Missing read ID")?;
-    if s1 != s2 {
-        return Err(anyhow!("Mismatch between IDs of read 1 and read 2"));
-    }
-
-    let reference = cfg.reference();
     let min_qual = cfg.min_qual();
-    let skip_mb_del = cfg.ignore_multibase_deletions();
-    let skip_mult_del = cfg.ignore_multiple_deletions();
-    let skip_mult_mut = cfg.ignore_multiple_mutations();
-    let skip_mult_mod = cfg.ignore_multiple_modifications();
     let max_divergence = cfg.max_overlap_divergence();
-    let ref_len = reference.len();
-    let len_range=ref_len.saturating_sub(cfg.max_length_divergence() as usize)..=ref_len + cfg.max_length_divergence() as usize;
-    
+    let free_ends = cfg.merge_free_ends() as i32;
+
+    // Remove adapter read through before merging
+    if cfg.trim_adapters() && trim_pair(cfg, &mut rec1, &mut rec2) {
+        stats.add_adapter_trimmed();
+        // Adapter dimers and very short inserts are not merged
+        if rec1.seq().len().min(rec2.seq().len()) < MIN_TRIMMED_LEN {
+            stats.add_trimmed_short();
+            return Ok(());
+        }
+    }
+
     // Reverse complement read 2 sequence
-    let v = aligner.buf_mut();
+    let v = merge_aligner.buf_mut();
     v.clear();
-    for c in rec2.seq().iter().rev().map(|c| match c {
-        b'A' | b'a' => b'T',
-        b'C' | b'c' => b'G',
-        b'G' | b'g' => b'C',
-        b'T' | b't' => b'A',
-        x => *x,
-    }) {
+    for c in rec2.seq().iter().rev().map(complement) {
         v.push(c)
     }
 
     // Set up aligner for overlapping reads
-    aligner.set_alignment_free_ends(0, 15, 15, 0);
+    merge_aligner.set_alignment_free_ends(0, free_ends, free_ends, 0);
 
     // Align read 1 and read 2 together
-    aligner
+    merge_aligner
         .align_buf_as_text(rec1.seq())
         .with_context(|| "Error when aligning overlap")?;
-    let cigar = aligner.wfs_aligner().cigar();
+    let cigar = merge_aligner.wfs_aligner().cigar();
+    let overlap = merge_ops(
+        cigar.operations(),
+        [rec1.seq(), rec1.qual()],
+        [merge_aligner.buf(), rec2.qual()],
+        qual_offset,
+        min_qual,
+        ov_buf,
+    );
+
+    // Pairs that can not be merged (no overlap, too many mismatches in the overlap, or a
+    // merged read that does not match the length of any amplicon) are joined if requested
+    let merged_len = ov_buf.len();
+    let overlapped = overlap.is_some_and(|mm| mm <= max_divergence);
+    if overlapped && process_merged(cfg, sample, stats, aligner, ov_buf, al_buf, view_data)? {
+        return Ok(());
+    }
+    if cfg.join_unmerged()
+        && process_joined(
+            cfg,
+            sample,
+            qual_offset,
+            &rec1,
+            &rec2,
+            stats,
+            aligner,
+            ov_buf,
+            al_buf,
+            view_data,
+        )?
+    {
+        return Ok(());
+    }
+    if overlapped {
+        stats.add_unmatched(merged_len as u32)
+    }
+    Ok(())
+}
 
-    let mut text_itr = aligner.buf().iter().zip(rec2.qual().iter().rev());
-    let mut patt_itr = rec1.seq().iter().zip(rec1.qual().iter());
+/// Merge read 1 (`r1`: sequence and qualities) and reverse complemented read 2 (`r2`: reverse
+/// complemented sequence and original qualities) into `ov_buf` following the CIGAR operations
+/// `ops` of the alignment of read 1 (pattern) to read 2 (text).  Returns the number of
+/// mismatches in the overlap, or None if the reads do not overlap
+///
+/// Bases before the start of the overlap that are only in read 1, and bases after the end
+/// of the overlap that are only in read 2, are kept with their own qualities.  Bases from
+/// the other ends of the reads (read through into adapter) and gaps within the overlap are
+/// dropped
+fn merge_ops(
+    ops: &[u8],
+    r1: [&[u8]; 2],
+    r2: [&[u8]; 2],
+    qual_offset: u8,
+    min_qual: u8,
+    ov_buf: &mut Vec<u8>,
+) -> Option<u32> {
+    let mut text_itr = r2[0].iter().zip(r2[1].iter().rev());
+    let mut patt_itr = r1[0].iter().zip(r1[1].iter());
+
+    let is_match = |c: &u8| matches!(c, b'M' | b'X');
+    let overlap = ops.iter().position(is_match).zip(ops.iter().rposition(is_match));
+    let (first, last) = overlap.map(|(i, j)| (i, j + 1)).unwrap_or((0, ops.len()));
+    let push_single = |v: &mut Vec<u8>, base: u8, q: u8| {
+        if q.saturating_sub(qual_offset) >= min_qual {
+            v.push(base)
+        } else {
+            v.push(b'N')
+        }
+    };
 
     ov_buf.clear();
     let mut mm = 0;
-    for op in cigar.operations() {
+    for (i, op) in ops.iter().enumerate() {
         match *op {
             b'M' | b'X' => {
                 let (t, qt) = text_itr.next().unwrap();
                 let (p, qp) = patt_itr.next().unwrap();
+                // Phred scores
+                let (qt, qp) = (qt.saturating_sub(qual_offset), qp.saturating_sub(qual_offset));
                 let (base, qual) = if t == p {
-                    (*t, *(qt.max(qp)))
+                    (*t, qt.max(qp))
                 } else if qt > qp {
                     mm += 1;
                     (*t, qt - qp)
@@ -112,62 +255,347 @@ fn process_records(
                     mm += 1;
                     (*p, qp - qt)
                 };
-                if qual.saturating_sub(33) >= min_qual {
+                if qual >= min_qual {
                     ov_buf.push(base)
                 } else {
                     ov_buf.push(b'N')
                 }
             }
             b'I' => {
-                let _ = text_itr.next();
+                let (t, qt) = text_itr.next().unwrap();
+                if i >= last {
+                    push_single(ov_buf, *t, *qt)
+                }
             }
             b'D' => {
-                let _ = patt_itr.next();
+                let (p, qp) = patt_itr.next().unwrap();
+                if i < first {
+                    push_single(ov_buf, *p, *qp)
+                }
             }
             _ => panic!("Unknown operation"),
         }
     }
-    
+    overlap.map(|_| mm)
+}
+
+/// Process read pair that could not be merged.  Read 1 is aligned to the start of the reference
+/// and (reverse complemented) read 2 to the end, and the reads are joined with a gap of `N`s
+/// covering the reference positions not covered by either read, so that these positions are
+/// not counted.  The amplicon with the lowest total alignment cost where the reads do not
+/// overlap on the reference and neither read has more differences from the reference than
+/// the maximum overlap divergence is chosen.  Returns false if no such amplicon is found
+#[allow(clippy::too_many_arguments)]
+fn process_joined(
+    cfg: &Config,
+    sample: usize,
+    qual_offset: u8,
+    rec1: &FastQRecord,
+    rec2: &FastQRecord,
+    stats: &mut SampleStats,
+    aligner: &mut Aligner,
+    ov_buf: &mut Vec<u8>,
+    al_buf: &mut Vec<u8>,
+    view_data: &mut Option<&mut ViewStore>,
+) -> anyhow::Result<bool> {
+    let min_qual = cfg.min_qual();
+    let max_divergence = cfg.max_overlap_divergence();
+    let mask = |base: u8, q: &u8| {
+        if q.saturating_sub(qual_offset) >= min_qual {
+            base
+        } else {
+            b'N'
+        }
+    };
+
+    // Masked read 1 followed by masked reverse complement of read 2
+    ov_buf.clear();
+    ov_buf.extend(rec1.seq().iter().zip(rec1.qual().iter()).map(|(b, q)| mask(*b, q)));
+    let l1 = ov_buf.len();
+    ov_buf.extend(
+        rec2.seq()
+            .iter()
+            .zip(rec2.qual().iter())
+            .rev()
+            .map(|(b, q)| mask(complement(b), q)),
+    );
+    let (r1, r2) = ov_buf.split_at(l1);
+    let l2 = r2.len();
+
+    // Check for primers and remove them from the reads
+    if let Some([p1, p2]) = cfg.primers()
+        && (l1 <= p1.len() || l2 <= p2.len())
+    {
+        stats.add_short();
+        return Ok(true);
+    }
+    let Some([p1, p2]) = find_primers(cfg, r1, r2) else {
+        stats.add_missing_primers();
+        return Ok(true);
+    };
+    let (r1, r2) = (&r1[p1..], &r2[..l2 - p2]);
+    if r1.is_empty() || r2.is_empty() {
+        return Ok(false);
+    }
+
+    let mut best: Option<(usize, i64, [usize; 2])> = None;
+    let mut ops = [Vec::new(), Vec::new()];
+    let mut best_ops = [Vec::new(), Vec::new()];
+    for (ix, a) in cfg.amplicons().iter().enumerate() {
+        let rf = a.target();
+        let ref_len = rf.len();
+        if r1.len() + r2.len() > ref_len + max_len_divergence(cfg, ref_len) {
+            continue;
+        }
+        // Read 1 from the start of the reference
+        aligner.set_alignment_free_ends(0, 0, 0, ref_len as i32);
+        aligner
+            .align(r1, rf)
+            .with_context(|| "Error when aligning read 1 to reference")?;
+        let end1 = ref_len
+            - trimmed_ops(aligner.wfs_aligner().cigar().operations(), &mut ops[0], false);
+        // Read 2 to the end of the reference
+        aligner.set_alignment_free_ends(0, 0, ref_len as i32, 0);
+        aligner
+            .align(r2, rf)
+            .with_context(|| "Error when aligning read 2 to reference")?;
+        let start2 = trimmed_ops(aligner.wfs_aligner().cigar().operations(), &mut ops[1], true);
+        if end1 > start2
+            || n_diffs(&ops[0], r1) > max_divergence
+            || n_diffs(&ops[1], r2) > max_divergence
+        {
+            continue;
+        }
+        let cost = aligner.ops_cost(&ops[0]) + aligner.ops_cost(&ops[1]);
+        if best.map(|(_, c, _)| cost < c).unwrap_or(true) {
+            best = Some((ix, cost, [end1, start2]));
+            std::mem::swap(&mut ops, &mut best_ops)
+        }
+    }
+    let Some((amp_ix, _, [end1, start2])) = best else {
+        return Ok(false);
+    };
+    stats.add_joined();
+    let amplicon = &cfg.amplicons()[amp_ix];
+    let reference = amplicon.target();
+    let offset = amplicon.target_range().start;
+    let stats = stats.amplicon_mut(amp_ix);
+
+    // Primer positions (if any) are masked
+    al_buf.clear();
+    al_buf.resize(offset, b'N');
+    let mut mods = Mods::default();
+    add_aligned(&best_ops[0], r1, &reference[..end1], stats, al_buf, &mut mods);
+    al_buf.resize(offset + start2, b'N');
+    add_aligned(&best_ops[1], r2, &reference[start2..], stats, al_buf, &mut mods);
+    al_buf.resize(amplicon.seq().len(), b'N');
+    let len = l1 + (start2 - end1) + l2;
+    add_read(cfg, sample, amp_ix, stats, al_buf, mods, len, view_data);
+    Ok(true)
+}
+
+/// Number of differences (mismatches other than at `N`s in `read`, and inserted or deleted
+/// bases) in an alignment of `read` with CIGAR operations `ops`
+fn n_diffs(ops: &[u8], read: &[u8]) -> u32 {
+    let mut patt_itr = read.iter();
+    let mut n = 0;
+    for op in ops {
+        match *op {
+            b'M' => {
+                let _ = patt_itr.next();
+            }
+            b'X' => {
+                if patt_itr.next() != Some(&b'N') {
+                    n += 1
+                }
+            }
+            b'D' => {
+                let _ = patt_itr.next();
+                n += 1
+            }
+            _ => n += 1,
+        }
+    }
+    n
+}
+
+/// Copy the CIGAR operations `o` to `ops`, removing the unaligned part of the reference from
+/// the start (if `at_start` is set) or the end of the alignment.  Returns the number of
+/// reference positions removed
+fn trimmed_ops(o: &[u8], ops: &mut Vec<u8>, at_start: bool) -> usize {
+    let is_ins = |c: &&u8| **c == b'I';
+    ops.clear();
+    if at_start {
+        let n = o.iter().take_while(is_ins).count();
+        ops.extend_from_slice(&o[n..]);
+        n
+    } else {
+        let n = o.iter().rev().take_while(is_ins).count();
+        ops.extend_from_slice(&o[..o.len() - n]);
+        n
+    }
+}
+
+/// Process single-end (or pre-merged) read, masking low quality bases.  If `orienter` is set
+/// (for long reads), the read is reverse complemented if required to match the amplicons
+#[allow(clippy::too_many_arguments)]
+fn process_single(
+    cfg: &Config,
+    sample: usize,
+    qual_offset: u8,
+    mut rec: FastQRecord,
+    orienter: Option<&Orienter>,
+    stats: &mut SampleStats,
+    aligner: &mut Aligner,
+    ov_buf: &mut Vec<u8>,
+    al_buf: &mut Vec<u8>,
+    view_data: &mut Option<&mut ViewStore>,
+) -> anyhow::Result<()> {
+    let min_qual = cfg.min_qual();
+    if cfg.trim_adapters() && trim_single(cfg, &mut rec) {
+        stats.add_adapter_trimmed();
+        if rec.seq().len() < MIN_TRIMMED_LEN {
+            stats.add_trimmed_short();
+            return Ok(());
+        }
+    }
+    ov_buf.clear();
+    for (base, qual) in rec.seq().iter().zip(rec.qual()) {
+        if qual.saturating_sub(qual_offset) >= min_qual {
+            ov_buf.push(*base)
+        } else {
+            ov_buf.push(b'N')
+        }
+    }
+    if let Some(o) = orienter {
+        match o.is_reversed(ov_buf) {
+            None => {
+                stats.add_unmatched(ov_buf.len() as u32);
+                return Ok(());
+            }
+            Some(true) => {
+                ov_buf.reverse();
+                for c in ov_buf.iter_mut() {
+                    *c = complement(c)
+                }
+                stats.add_reversed()
+            }
+            Some(false) => {}
+        }
+    }
+    if !process_merged(cfg, sample, stats, aligner, ov_buf, al_buf, view_data)? {
+        stats.add_unmatched(ov_buf.len() as u32)
+    }
+    Ok(())
+}
+
+#[inline]
+pub(super) fn complement(c: &u8) -> u8 {
+    match c {
+        b'A' | b'a' => b'T',
+        b'C' | b'c' => b'G',
+        b'G' | b'g' => b'C',
+        b'T' | b't' => b'A',
+        x => *x,
+    }
+}
+
+/// Align merged read in `ov_buf` to the reference and collect statistics.  Returns false
+/// (without counting the read) if the read does not match the length of any amplicon
+fn process_merged(
+    cfg: &Config,
+    sample: usize,
+    stats: &mut SampleStats,
+    aligner: &mut Aligner,
+    ov_buf: &[u8],
+    al_buf: &mut Vec<u8>,
+    view_data: &mut Option<&mut ViewStore>,
+) -> anyhow::Result<bool> {
+    // Check for primers and remove them from the read
     let l = ov_buf.len();
-    if mm > max_divergence || !len_range.contains(&l) {
-        return Ok(())
+    if let Some([p1, p2]) = cfg.primers()
+        && l <= p1.len() + p2.len()
+    {
+        stats.add_short();
+        return Ok(true);
     }
+    let Some([p1, p2]) = find_primers(cfg, ov_buf, ov_buf) else {
+        stats.add_missing_primers();
+        return Ok(true);
+    };
+    // The primers can overlap if they are found at shifted positions
+    let Some(read) = ov_buf.get(p1..l - p2).filter(|r| !r.is_empty()) else {
+        stats.add_short();
+        return Ok(true);
+    };
+
     // Set up for end-to-end alignment
     aligner.set_alignment_free_ends(0, 0, 0, 0);
 
-    aligner
-        .align(ov_buf, reference)
-        .with_context(|| "Error when aligning to reference")?;
+    // Find the amplicon that best matches the merged read
+    let Some(amp_ix) = assign_amplicon(cfg, read, aligner)? else {
+        return Ok(false);
+    };
+    let amplicon = &cfg.amplicons()[amp_ix];
+    let stats = stats.amplicon_mut(amp_ix);
 
+    // Primer positions (if any) are masked
     let cigar = aligner.wfs_aligner().cigar();
-    let mut patt_itr = ov_buf.iter();
-    let mut text_itr = reference.iter();
     al_buf.clear();
-    let mut start_del = None;
-    let mut n_del = 0;
-    let mut n_mut = 0;
-    let mut mb_del = false;
-    for op in cigar.operations() {
+    al_buf.resize(amplicon.target_range().start, b'N');
+    let mut mods = Mods::default();
+    add_aligned(cigar.operations(), read, amplicon.target(), stats, al_buf, &mut mods);
+    al_buf.resize(amplicon.seq().len(), b'N');
+    add_read(cfg, sample, amp_ix, stats, al_buf, mods, l, view_data);
+    Ok(true)
+}
+
+/// Modifications relative to the reference found in a read
+#[derive(Default)]
+struct Mods {
+    /// Start of current deletion (if any)
+    start_del: Option<usize>,
+    n_del: u32,
+    n_mut: u32,
+    /// Deletion of more than one base
+    mb_del: bool,
+}
+
+/// Add the bases of `read` to `al_buf` following the CIGAR operations `ops` of the alignment
+/// of `read` (pattern) to `reference` (text), so that each entry in `al_buf` corresponds to
+/// a reference position.  Deleted positions are marked with ' ', and bases followed by an
+/// insertion are converted to lower case.  Deletions are added to `stats` and counted in `mods`
+fn add_aligned(
+    ops: &[u8],
+    read: &[u8],
+    reference: &[u8],
+    stats: &mut Stats,
+    al_buf: &mut Vec<u8>,
+    mods: &mut Mods,
+) {
+    let mut patt_itr = read.iter();
+    let mut text_itr = reference.iter();
+    for op in ops {
         match *op {
             b'M' | b'X' => {
                 let r = text_itr.next().unwrap();
                 let p = patt_itr.next().unwrap();
-                if let Some(x) = start_del.take() {
+                if let Some(x) = mods.start_del.take() {
                     stats.add_del(x, al_buf.len())
                 }
                 if *r != *p && *p != b'N'{
-                    n_mut += 1
+                    mods.n_mut += 1
                 }
                 al_buf.push(p.to_ascii_uppercase());
             }
             b'I' => {
                 let _ = text_itr.next();
                 al_buf.push(b' ');
-                if start_del.is_some() {
-                    mb_del = true
+                if mods.start_del.is_some() {
+                    mods.mb_del = true
                 } else {
-                    start_del = Some(al_buf.len());
-                    n_del += 1;
+                    mods.start_del = Some(al_buf.len());
+                    mods.n_del += 1;
                 }
             }
             b'D' => {
@@ -175,21 +603,41 @@ fn process_records(
                 if let Some(e) = al_buf.last_mut() {
                     *e = e.to_ascii_lowercase()
                 }
-                if let Some(x) = start_del.take() {
+                if let Some(x) = mods.start_del.take() {
                     stats.add_del(x, al_buf.len())
                 }
             }
             _ => panic!("Unknown operation"),
         }
     }
+}
+
+/// Collect statistics for a read aligned to amplicon `amp_ix` (in `al_buf`) with modifications
+/// `mods`, where `len` is the length of the read
+#[allow(clippy::too_many_arguments)]
+fn add_read(
+    cfg: &Config,
+    sample: usize,
+    amp_ix: usize,
+    stats: &mut Stats,
+    al_buf: &mut [u8],
+    mut mods: Mods,
+    len: usize,
+    view_data: &mut Option<&mut ViewStore>,
+) {
+    let skip_mb_del = cfg.ignore_multibase_deletions();
+    let skip_mult_del = cfg.ignore_multiple_deletions();
+    let skip_mult_mut = cfg.ignore_multiple_mutations();
+    let skip_mult_mod = cfg.ignore_multiple_modifications();
 
     al_buf[0] = al_buf[0].to_ascii_uppercase();
     let ix = al_buf.len();
     al_buf[ix - 1] = al_buf[ix - 1].to_ascii_uppercase();
-    if let Some(x) = start_del.take() {
+    if let Some(x) = mods.start_del.take() {
         stats.add_del(x, al_buf.len())
     }
 
+    let Mods { n_mut, n_del, mb_del, .. } = mods;
     stats.add_mut_and_del_counts(n_mut, n_del);
     let skip = (skip_mb_del && mb_del)
         || (skip_mult_mut && n_mut > 1)
@@ -199,7 +647,7 @@ fn process_records(
     if !skip {
         stats.add_obs(al_buf.as_ref());
         if let Some(vs) = view_data.as_mut() {
-            let mut v_itr = vs.next_view().iter_mut();
+            let mut v_itr = vs.next_view(sample, amp_ix).iter_mut();
             for p in al_buf.iter() {
                 if let Some(q) = v_itr.next() {
                     *q = *p
@@ -212,6 +660,152 @@ fn process_records(
             }
         }
     }
-    stats.add_len(ov_buf.len() as u32);
-    Ok(())
+    stats.add_len(len as u32);
+}
+
+/// Select the amplicon that best matches the merged read in `ov_buf`.  Candidate amplicons
+/// are those where the read length is within the allowed divergence of the reference length;
+/// if there is more than one candidate, the read is aligned to each and the lowest cost alignment
+/// is chosen.  On return, the last alignment performed by `aligner` is to the chosen amplicon.
+/// Returns None if no amplicon matches.
+fn assign_amplicon(
+    cfg: &Config,
+    ov_buf: &[u8],
+    aligner: &mut Aligner,
+) -> anyhow::Result<Option<usize>> {
+    let l = ov_buf.len();
+    let mut best: Option<(usize, i64)> = None;
+    let mut last = None;
+    for (ix, a) in cfg.amplicons().iter().enumerate() {
+        let ref_len = a.target().len();
+        if l.abs_diff(ref_len) > max_len_divergence(cfg, ref_len) {
+            continue;
+        }
+        aligner
+            .align(ov_buf, a.target())
+            .with_context(|| "Error when aligning to reference")?;
+        last = Some(ix);
+        let cost = aligner.cost();
+        if best.map(|(_, c)| cost < c).unwrap_or(true) {
+            best = Some((ix, cost))
+        }
+    }
+
+    // Redo the alignment if the best match was not the last one tried
+    if let Some((ix, _)) = best
+        && last != Some(ix)
+    {
+        aligner
+            .align(ov_buf, cfg.amplicons()[ix].target())
+            .with_context(|| "Error when aligning to reference")?;
+    }
+    Ok(best.map(|(ix, _)| ix))
+}
+
+/// Maximum difference allowed between the length of a merged read and an amplicon of length
+/// `ref_len`.  For long reads, this is (at least) a fixed fraction of the amplicon length
+fn max_len_divergence(cfg: &Config, ref_len: usize) -> usize {
+    let d = cfg.max_length_divergence() as usize;
+    if cfg.long_reads() {
+        d.max((ref_len as f64 * cfg.long_read_length_window()).ceil() as usize)
+    } else {
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_record_limit() {
+        let n_bad = AtomicU64::new(0);
+        let e = || anyhow!("Invalid record");
+        assert!(skip_bad_record(0, &n_bad, e()).is_err());
+        assert_eq!(n_bad.load(Ordering::Relaxed), 0);
+
+        assert!(skip_bad_record(2, &n_bad, e()).is_ok());
+        assert!(skip_bad_record(2, &n_bad, e()).is_ok());
+        let Err(e) = skip_bad_record(2, &n_bad, e()) else {
+            panic!("Limit on bad records not applied")
+        };
+        assert!(e.to_string().contains("limit 2"));
+    }
+
+    /// Merge `r1` with `t2` (reverse complemented read 2) given qualities `qt2` in the order
+    /// of `t2`, using a quality offset of 33 and a minimum quality of 20
+    fn merge(ops: &[u8], r1: &[u8], q1: &[u8], t2: &[u8], qt2: &[u8]) -> (Option<u32>, Vec<u8>) {
+        let q2: Vec<u8> = qt2.iter().rev().copied().collect();
+        let mut v = Vec::new();
+        let mm = merge_ops(ops, [r1, q1], [t2, &q2], 33, 20, &mut v);
+        (mm, v)
+    }
+
+    #[test]
+    fn merge_keeps_single_read_ends() {
+        let (r1, t2) = (b"TTACGTAC", b"ACGTACGG");
+        let ops = b"DDMMMMMMII";
+        assert_eq!(
+            merge(ops, r1, b"IIIIIIII", t2, b"IIIIIIII"),
+            (Some(0), b"TTACGTACGG".to_vec())
+        );
+        // Low quality bases outside of the overlap are masked
+        assert_eq!(
+            merge(ops, r1, b"#IIIIIII", t2, b"IIIIIII#"),
+            (Some(0), b"NTACGTACGN".to_vec())
+        );
+    }
+
+    #[test]
+    fn merge_drops_read_through_and_gaps() {
+        // Read 1 runs into adapter after the end of read 2 and vice versa
+        let q = b"IIIIIIII";
+        assert_eq!(
+            merge(b"IIMMMMMMDD", b"ACGTACAA", q, b"GGACGTAC", q),
+            (Some(0), b"ACGTAC".to_vec())
+        );
+        // Base only in read 1 within the overlap
+        assert_eq!(
+            merge(b"MMMDMMM", b"ACGGTAC", &q[..7], b"ACGTAC", &q[..6]),
+            (Some(0), b"ACGTAC".to_vec())
+        );
+    }
+
+    #[test]
+    fn merge_mismatches() {
+        let (r1, t2) = (b"ACGTAC", b"ACCTAC");
+        // Base with the higher quality is used if the difference in quality is high enough
+        assert_eq!(
+            merge(b"MXMMMM", r1, b"IIIIII", t2, b"II+III"),
+            (Some(1), b"ACGTAC".to_vec())
+        );
+        assert_eq!(
+            merge(b"MXMMMM", r1, b"IIIIII", t2, b"IIIIII"),
+            (Some(1), b"ACNTAC".to_vec())
+        );
+        // No overlap
+        assert_eq!(merge(b"DDDII", b"ACG", b"III", b"TT", b"II").0, None);
+    }
+
+    #[test]
+    fn trim_unaligned_reference() {
+        let mut ops = Vec::new();
+        // Read 1 aligned to the start of the reference
+        assert_eq!(trimmed_ops(b"MMXMDMMIII", &mut ops, false), 3);
+        assert_eq!(ops, b"MMXMDMM");
+        // Read 2 aligned to the end of the reference
+        assert_eq!(trimmed_ops(b"IIMMIMM", &mut ops, true), 2);
+        assert_eq!(ops, b"MMIMM");
+        assert_eq!(trimmed_ops(b"MMMM", &mut ops, true), 0);
+        assert_eq!(ops, b"MMMM");
+    }
+
+    #[test]
+    fn count_differences() {
+        // Mismatches at Ns in the read are not counted
+        assert_eq!(n_diffs(b"MMXMXM", b"ACNTGA"), 1);
+        // Inserted and deleted bases are counted
+        assert_eq!(n_diffs(b"MMDMIMM", b"ACGTAC"), 2);
+        assert_eq!(n_diffs(b"MMMM", b"ACGT"), 0);
+    }
 }
@@ -1,32 +1,52 @@
+use std::sync::{Mutex, atomic::AtomicU64};
+
 use anyhow::Context;
 use crossbeam_channel::{Receiver, Sender};
 
-use super::{super::view::{ViewBuf, ViewStore}, process_buffer::process_buffer};
+use super::{super::view::{SampleProgress, ViewMsg, ViewStore}, process_buffer::process_buffer};
 
 use crate::{
     cli::Config,
-    process::{Buffer, align::Aligner, counts::Stats},
+    process::{Buffer, align::Aligner, counts::SampleStats, orient::Orienter},
 };
 
+/// Process buffers received on `rx`, adding the results to the shared per sample statistics in
+/// `stats`.
+///
+/// The thread only keeps statistics for the sample of the buffer being processed, and adds
+/// these to `stats` when a buffer from a different sample is received.  This limits memory
+/// use, as the statistics for an amplicon of length L include O(L²) mutation correlation
+/// counts, so keeping statistics for every sample in every thread would need memory
+/// proportional to threads × samples × amplicons × L².  In the same way, view records for a
+/// sample are sent to the view writer when the thread moves on to a different sample
+#[allow(clippy::too_many_arguments)]
 pub fn process_thread<'a>(
     cfg: &'a Config,
     ix: usize,
     rx: Receiver<Buffer>,
     sx: Sender<Buffer>,
-    mut sx_view: Option<Sender<ViewBuf>>,
-) -> anyhow::Result<Stats<'a>> {
+    sx_view: Option<(Sender<ViewMsg>, &SampleProgress)>,
+    n_bad: &AtomicU64,
+    orienter: Option<&Orienter>,
+    stats: &Mutex<Vec<SampleStats<'a>>>,
+) -> anyhow::Result<()> {
     debug!("Starting up process thread {ix}");
 
-    let mut stats = Stats::new(cfg.reference());
+    // Sample index, statistics and number of buffers processed for the current sample
+    let mut current: Option<(usize, SampleStats, usize)> = None;
 
-    let mut aligner = Aligner::default();
+    // Separate aligners are used for merging read pairs and for alignment to the reference
+    let mut merge_aligner = Aligner::new(cfg.merge_penalties());
+    let mut aligner = Aligner::new(cfg.ref_penalties());
 
-    let mut view_data = sx_view.take().map(|s| {
-       ViewStore::new(cfg.reference().len(), s) 
+    let mut view_data = sx_view.map(|(s, p)| {
+       let v = ViewStore::new(cfg.n_samples(), cfg.amplicons().iter().map(|a| a.seq().len()).collect(), s);
+       (v, p)
     });
 
-    let mut overlap_buf = Vec::with_capacity(cfg.reference().len());
-    let mut al_buf = Vec::with_capacity(cfg.reference().len());
+    let max_len = cfg.amplicons().iter().map(|a| a.seq().len()).max().unwrap_or(0);
+    let mut overlap_buf = Vec::with_capacity(max_len);
+    let mut al_buf = Vec::with_capacity(max_len);
 
     while let Ok(mut b) = rx.recv() {
         if b.is_empty() {
@@ -35,17 +55,30 @@ pub fn process_thread<'a>(
                 b.ix()
             )
         } else {
-            trace!("Process thread {ix} received new buffer {}", b.ix());
+            trace!(
+                "Process thread {ix} received new buffer {} (sample {})",
+                b.ix(),
+                b.sample()
+            );
+            let sample = b.sample();
+            if let Some(x) = current.take_if(|(s, _, _)| *s != sample) {
+                finish_sample(stats, view_data.as_mut(), x)
+            }
+            let (_, st, n) = current.get_or_insert_with(|| (sample, SampleStats::new(cfg), 0));
             process_buffer(
                 cfg,
                 &b,
+                &mut merge_aligner,
                 &mut aligner,
-                &mut stats,
+                st,
                 &mut overlap_buf,
                 &mut al_buf,
-                view_data.as_mut(),
+                view_data.as_mut().map(|(v, _)| v),
+                n_bad,
+                orienter,
             )
             .with_context(|| format!("Process thread {ix}: Error parsing input buffer"))?;
+            *n += 1;
 
             trace!(
                 "Process thread {ix} finished processing block; sending empty block {} back to reader",
@@ -59,6 +92,22 @@ pub fn process_thread<'a>(
         let _ = sx.send(b);
     }
     debug!("Closing down process thread {ix}");
+    if let Some(x) = current {
+        finish_sample(stats, view_data.as_mut(), x)
+    }
 
-    Ok(stats)
+    Ok(())
+}
+
+/// Add the statistics `st` for sample `sample` to the shared statistics, and send any view
+/// records for the sample to the view writer, marking the `n` buffers processed as finished
+fn finish_sample<'a>(
+    stats: &Mutex<Vec<SampleStats<'a>>>,
+    view_data: Option<&mut (ViewStore, &SampleProgress)>,
+    (sample, st, n): (usize, SampleStats<'a>, usize),
+) {
+    stats.lock().expect("Error locking sample statistics")[sample] += st;
+    if let Some((v, p)) = view_data {
+        v.finish_sample(sample, n, p)
+    }
 }
@@ -0,0 +1,99 @@
+use crate::cli::Pairing;
+
+/// Result of comparing the IDs of read 1 and read 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum IdMatch {
+    /// IDs match without modification (or are not checked)
+    Exact,
+    /// IDs match after removing /1 and /2 suffixes
+    Suffix,
+    Mismatch,
+}
+
+/// Compare the IDs (header line without the leading '@') of read 1 and read 2 using the
+/// rule given by `pairing`
+pub(super) fn match_ids(pairing: Pairing, id1: &[u8], id2: &[u8]) -> IdMatch {
+    if pairing == Pairing::None {
+        return IdMatch::Exact;
+    }
+    let (t1, t2) = (first_token(id1), first_token(id2));
+    if t1 == t2 {
+        return IdMatch::Exact;
+    }
+    if pairing == Pairing::Strict {
+        return IdMatch::Mismatch;
+    }
+
+    // Remove /1 and /2 suffixes
+    let (s1, s2) = match (t1.strip_suffix(b"/1"), t2.strip_suffix(b"/2")) {
+        (Some(s1), Some(s2)) => (s1, s2),
+        _ => (t1, t2),
+    };
+    let suffix = s1.len() != t1.len();
+
+    let matched = if pairing == Pairing::Illumina {
+        illumina_prefix(s1) == illumina_prefix(s2)
+    } else {
+        s1 == s2
+    };
+    match (matched, suffix) {
+        (false, _) => IdMatch::Mismatch,
+        (true, true) => IdMatch::Suffix,
+        (true, false) => IdMatch::Exact,
+    }
+}
+
+/// First whitespace delimited token of the ID
+pub(super) fn first_token(id: &[u8]) -> &[u8] {
+    id.split(|c| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default()
+}
+
+/// instrument:run:flowcell:lane:tile:x:y part of an Illumina read ID (the whole ID if it
+/// has fewer than 7 fields)
+fn illumina_prefix(id: &[u8]) -> &[u8] {
+    match id.iter().enumerate().filter(|(_, c)| **c == b':').nth(6) {
+        Some((i, _)) => &id[..i],
+        None => id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_pairing() {
+        let p = Pairing::Strict;
+        assert_eq!(match_ids(p, b"r1 1:N:0:1", b"r1 2:N:0:1"), IdMatch::Exact);
+        assert_eq!(match_ids(p, b"r1/1", b"r1/2"), IdMatch::Mismatch);
+        assert_eq!(match_ids(Pairing::None, b"r1", b"r2"), IdMatch::Exact);
+    }
+
+    #[test]
+    fn suffix_pairing() {
+        let p = Pairing::StripSuffix;
+        assert_eq!(match_ids(p, b"r1/1", b"r1/2"), IdMatch::Suffix);
+        assert_eq!(match_ids(p, b"r1/1 x", b"r1/2 y"), IdMatch::Suffix);
+        assert_eq!(match_ids(p, b"r1/2", b"r1/1"), IdMatch::Mismatch);
+        assert_eq!(match_ids(p, b"r1/1", b"r2/2"), IdMatch::Mismatch);
+    }
+
+    #[test]
+    fn illumina_pairing() {
+        let p = Pairing::Illumina;
+        assert_eq!(
+            match_ids(p, b"M:7:F:1:11:15:20:ACGT", b"M:7:F:1:11:15:20:TTGA"),
+            IdMatch::Exact
+        );
+        assert_eq!(
+            match_ids(p, b"M:7:F:1:11:15:20/1", b"M:7:F:1:11:15:20/2"),
+            IdMatch::Suffix
+        );
+        assert_eq!(
+            match_ids(p, b"M:7:F:1:11:15:20", b"M:7:F:1:11:15:21"),
+            IdMatch::Mismatch
+        );
+    }
+}
@@ -1,14 +1,60 @@
-use std::{path::Path, thread};
+use std::{
+    io::{self, Read},
+    path::Path,
+    sync::{Arc, atomic::AtomicU8},
+    thread::{self, Scope},
+};
 
 use anyhow::Context;
 use compress_io::compress::CompressIo;
 use crossbeam_channel::{Receiver, Sender, bounded};
+use memmap2::Mmap;
 
 mod buffer;
+mod fasta;
+mod interleaved;
+mod mapped;
+mod qual_offset;
+mod read_ahead;
+mod sam;
 
 pub use buffer::Buffer;
+use buffer::{FillStatus, count_records};
+use fasta::FastaReader;
+use interleaved::Interleaved;
+use mapped::map_input;
+use qual_offset::input_qual_offset;
+use read_ahead::ReadAhead;
+use sam::SamReader;
 
-use crate::cli::Config;
+use super::view::{SampleProgress, ViewMsg};
+use crate::cli::{Config, InputFormat, InputMode, Pairing, is_stdin};
+
+/// Sender for full buffers.  If view files are written, the buffers pending for each sample are
+/// counted so that the view writer can tell when a sample is complete
+#[derive(Clone)]
+struct BufferSender<'a> {
+    snd: Sender<Buffer>,
+    views: Option<(Sender<ViewMsg>, &'a SampleProgress)>,
+}
+
+impl BufferSender<'_> {
+    fn send(&self, b: Buffer) -> anyhow::Result<()> {
+        if let Some((_, p)) = self.views.as_ref()
+            && !b.is_empty()
+        {
+            p.add_buffer(b.sample())
+        }
+        self.snd.send(b).map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Mark an input for `sample` as completely read
+    fn input_done(&self, sample: usize) {
+        if let Some((s, p)) = self.views.as_ref() {
+            p.done(sample, 1, s)
+        }
+    }
+}
 
 fn get_buffer(recv: &Receiver<Buffer>) -> anyhow::Result<Buffer> {
     let mut b = recv
@@ -19,10 +65,13 @@ fn get_buffer(recv: &Receiver<Buffer>) -> anyhow::Result<Buffer> {
 }
 
 pub fn create_buffers(cfg: &Config, snd: &Sender<Buffer>) -> anyhow::Result<()> {
-    let nb = cfg.threads().max(cfg.readers()) << 2;
-    debug!("Number of buffers: {nb}");
+    let nb = cfg.buffers();
+    let (size, max_size) = (cfg.buffer_size(), cfg.max_buffer_size());
+    debug!(
+        "Number of buffers: {nb}, initial size: {size} bytes, maximum size: {max_size} bytes"
+    );
     for ix in 0..nb {
-        snd.send(Buffer::new(ix))?
+        snd.send(Buffer::new(ix, size, max_size))?
     }
     Ok(())
 }
@@ -31,18 +80,74 @@ fn read_thread(
     cfg: &Config,
     reader_ix: usize,
     rcv_buf: Receiver<Buffer>,
-    snd_buf: Sender<Buffer>,
+    snd_buf: BufferSender,
     recv_file: Receiver<usize>,
+    qual_offset: &AtomicU8,
 ) -> anyhow::Result<()> {
     let mut pending: Option<Buffer> = None;
 
     debug!("Reader {reader_ix} starting up");
 
     while let Ok(ix) = recv_file.recv() {
-        let f1 = &cfg.input_files()[ix << 1];
-        let f2 = &cfg.input_files()[1 + (ix << 1)];
-        read_from_fastq(reader_ix, f1, f2, &mut pending, &rcv_buf, &snd_buf)?;
-        debug!("Reader {reader_ix}: Finished reading input file pair {ix}");
+        let input = &cfg.inputs()[ix];
+        let files: Vec<_> = input.files().collect();
+        let sample = input.sample();
+        if let Some(name) = cfg.sample_name(sample) {
+            debug!("Reader {reader_ix}: Reading input {ix} for sample {name}");
+        }
+        if let Some(maps) = map_input(cfg, &files)? {
+            read_from_mmap(
+                cfg,
+                reader_ix,
+                ix,
+                &files,
+                &maps,
+                &mut pending,
+                &rcv_buf,
+                &snd_buf,
+                qual_offset,
+            )?;
+            debug!("Reader {reader_ix}: Finished reading input {ix}");
+            snd_buf.input_done(sample);
+            continue;
+        }
+        thread::scope(|scope| {
+            // Streams must be read by separate threads so that they are consumed independently.
+            // Otherwise, using a separate thread per file allows the files of a pair to be
+            // read and decompressed in parallel with each other and with record indexing
+            let read_ahead = input.is_stream() || cfg.read_ahead();
+            let mut rdr = files
+                .iter()
+                .map(|f| open_reader(cfg, scope, f, read_ahead))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            match cfg.input_mode() {
+                InputMode::Paired | InputMode::Single => read_from_fastq(
+                    cfg,
+                    reader_ix,
+                    ix,
+                    &files,
+                    &mut rdr,
+                    &mut pending,
+                    &rcv_buf,
+                    &snd_buf,
+                    qual_offset,
+                ),
+                InputMode::Interleaved => read_from_interleaved(
+                    cfg,
+                    reader_ix,
+                    ix,
+                    files[0],
+                    rdr.pop().expect("Missing input reader"),
+                    &mut pending,
+                    &rcv_buf,
+                    &snd_buf,
+                    qual_offset,
+                ),
+            }
+        })?;
+        debug!("Reader {reader_ix}: Finished reading input {ix}");
+        snd_buf.input_done(sample);
     }
 
     if let Some(buf) = pending.take() {
@@ -56,24 +161,20 @@ fn read_thread(
     Ok(())
 }
 
-fn read_from_fastq(
+#[allow(clippy::too_many_arguments)]
+fn read_from_fastq<R: Read>(
+    cfg: &Config,
     reader_ix: usize,
-    f1: &Path,
-    f2: &Path,
+    input_ix: usize,
+    files: &[&Path],
+    rdr: &mut [R],
     buf_store: &mut Option<Buffer>,
     rcv_buf: &Receiver<Buffer>,
-    snd_buf: &Sender<Buffer>,
+    snd_buf: &BufferSender,
+    run_qual_offset: &AtomicU8,
 ) -> anyhow::Result<()> {
-    let open_file = |f| {
-        CompressIo::new()
-            .path(f)
-            .reader()
-            .with_context(|| "Could not open input file")
-    };
-
-    let mut rdr1 = open_file(f1)?;
-    let mut rdr2 = open_file(f2)?;
-    info!("Reader {reader_ix}: opened input files ({f1:?}, {f2:?})");
+    info!("Reader {reader_ix}: opened input files {files:?}");
+    let sample = cfg.inputs()[input_ix].sample();
 
     let mut pending = match buf_store.take() {
         Some(b) => {
@@ -85,17 +186,49 @@ fn read_from_fastq(
             get_buffer(rcv_buf)?
         }
     };
+    pending.set_sample(sample);
+    let mut n_rec = 0;
+    let mut line = [0; 2];
+    let mut offset = [0; 2];
+    let mut qual_offset = None;
+
     // Main loop - read files until empty
     loop {
         let mut b = pending;
+        b.set_origin(input_ix, n_rec as u64, line, offset);
         pending = get_buffer(rcv_buf)?;
+        pending.set_sample(sample);
 
-        let eof = b.fill([&mut rdr1, &mut rdr2], &mut pending)?;
+        let status = b
+            .fill(rdr, &mut pending)
+            .with_context(|| format!("Error reading input files {files:?}"))?;
         trace!("Filled buffer: used {:?}", b.used());
+        let q = match qual_offset {
+            Some(q) => q,
+            None => *qual_offset.insert(input_qual_offset(cfg, &b, files, run_qual_offset)?),
+        };
+        b.set_qual_offset(q);
+        pending.set_qual_offset(q);
+        n_rec += b.n_records();
+        for (o, u) in offset.iter_mut().zip(b.used()) {
+            *o += *u as u64
+        }
+        for (l, n) in line.iter_mut().zip(b.n_lines()) {
+            *l += *n as u64
+        }
         snd_buf
             .send(b)
             .with_context(|| "Error sending full buffer")?;
-        if eof {
+        if let FillStatus::Unequal(i) = status {
+            let extra = count_records(pending.as_slices_ix(i), &mut rdr[i])?;
+            unequal_records(cfg, files, n_rec, i, extra)?;
+            pending.clear();
+            *buf_store = Some(pending);
+            break;
+        }
+        if status == FillStatus::Eof {
+            pending.set_origin(input_ix, n_rec as u64, line, offset);
+            n_rec += pending.n_records();
             if !pending.is_empty() {
                 snd_buf
                     .send(pending)
@@ -106,13 +239,208 @@ fn read_from_fastq(
             break;
         }
     }
-    info!("Reader {reader_ix} Finished reading input files ({f1:?}, {f2:?})");
+    info!("Reader {reader_ix} Finished reading input files {files:?}: {n_rec} record(s)");
+    Ok(())
+}
+
+/// Read from memory mapped input files.  The buffers refer to regions of the mapped files,
+/// so no data is copied
+#[allow(clippy::too_many_arguments)]
+fn read_from_mmap(
+    cfg: &Config,
+    reader_ix: usize,
+    input_ix: usize,
+    files: &[&Path],
+    maps: &[Arc<Mmap>],
+    buf_store: &mut Option<Buffer>,
+    rcv_buf: &Receiver<Buffer>,
+    snd_buf: &BufferSender,
+    run_qual_offset: &AtomicU8,
+) -> anyhow::Result<()> {
+    info!("Reader {reader_ix}: memory mapped input files {files:?}");
+    let sample = cfg.inputs()[input_ix].sample();
+    let mut pos = [0; 2];
+    let mut n_rec = 0;
+    let mut line = [0; 2];
+    let mut qual_offset = None;
+
+    let mut b = match buf_store.take() {
+        Some(b) => b,
+        None => get_buffer(rcv_buf)?,
+    };
+
+    // Main loop - index mapped files until all records have been handed out
+    loop {
+        b.set_sample(sample);
+        b.set_origin(input_ix, n_rec as u64, line, pos.map(|p| p as u64));
+        let status = b
+            .fill_mapped(maps, &mut pos)
+            .with_context(|| format!("Error reading input files {files:?}"))?;
+        trace!("Filled buffer from mapped files: positions {pos:?}");
+        let q = match qual_offset {
+            Some(q) => q,
+            None => *qual_offset.insert(input_qual_offset(cfg, &b, files, run_qual_offset)?),
+        };
+        b.set_qual_offset(q);
+        n_rec += b.n_records();
+        for (l, n) in line.iter_mut().zip(b.n_lines()) {
+            *l += *n as u64
+        }
+        if b.is_empty() {
+            *buf_store = Some(b)
+        } else {
+            snd_buf
+                .send(b)
+                .with_context(|| "Error sending full buffer")?;
+        }
+        match status {
+            FillStatus::More => b = get_buffer(rcv_buf)?,
+            FillStatus::Eof => break,
+            FillStatus::Unequal(i) => {
+                let extra = count_records(&maps[i][pos[i]..], &mut io::empty())?;
+                unequal_records(cfg, files, n_rec, i, extra)?;
+                break;
+            }
+        }
+    }
+    info!("Reader {reader_ix} Finished reading input files {files:?}: {n_rec} record(s)");
+    Ok(())
+}
+
+/// Report that input file `i` has `extra` records more than the other file of the pair, which
+/// has `n_rec` records.  This is an error unless unequal inputs are allowed
+fn unequal_records(
+    cfg: &Config,
+    files: &[&Path],
+    n_rec: usize,
+    i: usize,
+    extra: usize,
+) -> anyhow::Result<()> {
+    let mut counts = [n_rec; 2];
+    counts[i] += extra;
+    let msg = format!(
+        "Input files {:?} and {:?} have unequal numbers of records ({} and {})",
+        files[0], files[1], counts[0], counts[1]
+    );
+    if !cfg.allow_unequal_records() {
+        return Err(anyhow!(msg));
+    }
+    warn!("{msg}: ignoring {extra} unmatched record(s) from {:?}", files[i]);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_from_interleaved<R: Read>(
+    cfg: &Config,
+    reader_ix: usize,
+    input_ix: usize,
+    f: &Path,
+    rdr: R,
+    buf_store: &mut Option<Buffer>,
+    rcv_buf: &Receiver<Buffer>,
+    snd_buf: &BufferSender,
+    run_qual_offset: &AtomicU8,
+) -> anyhow::Result<()> {
+    // If invalid records can be skipped, read IDs are only checked by the process threads
+    let pairing = if cfg.max_bad_records() > 0 {
+        Pairing::None
+    } else {
+        cfg.pairing()
+    };
+    // Staging buffer holds (at least) a pair of records
+    let mut rdr = Interleaved::new(
+        rdr,
+        cfg.buffer_size() << 1,
+        cfg.max_buffer_size() << 1,
+        pairing,
+    );
+    info!("Reader {reader_ix}: opened interleaved input file {f:?}");
+    let sample = cfg.inputs()[input_ix].sample();
+    let mut qual_offset = None;
+
+    let mut b = match buf_store.take() {
+        Some(b) => b,
+        None => get_buffer(rcv_buf)?,
+    };
+
+    // Main loop - read file until empty
+    loop {
+        b.set_sample(sample);
+        b.set_origin_interleaved(input_ix, rdr.n_pairs() as u64);
+        let eof = rdr
+            .fill(&mut b)
+            .with_context(|| format!("Error reading interleaved input file {f:?}"))?;
+        trace!("Filled buffer: used {:?}", b.used());
+        let q = match qual_offset {
+            Some(q) => q,
+            None => *qual_offset.insert(input_qual_offset(cfg, &b, &[f], run_qual_offset)?),
+        };
+        b.set_qual_offset(q);
+        if eof {
+            if !b.is_empty() {
+                snd_buf
+                    .send(b)
+                    .with_context(|| "Error sending full buffer")?;
+            } else {
+                *buf_store = Some(b)
+            }
+            break;
+        }
+        snd_buf
+            .send(b)
+            .with_context(|| "Error sending full buffer")?;
+        b = get_buffer(rcv_buf)?;
+    }
+    info!("Reader {reader_ix} Finished reading interleaved input file {f:?}");
     Ok(())
 }
 
-pub fn reader(cfg: &Config, rcv: Receiver<Buffer>, snd: Sender<Buffer>) -> anyhow::Result<()> {
+/// Open input, optionally using a separate read ahead thread
+fn open_reader<'scope, 'env>(
+    cfg: &'env Config,
+    scope: &'scope Scope<'scope, 'env>,
+    f: &'env Path,
+    read_ahead: bool,
+) -> anyhow::Result<Box<dyn Read + 'env>> {
+    if read_ahead {
+        Ok(Box::new(ReadAhead::spawn(scope, move || open_input(cfg, f))))
+    } else {
+        open_input(cfg, f)
+    }
+}
+
+/// Open input file, converting to FASTQ if required
+fn open_input<'a>(cfg: &Config, f: &'a Path) -> anyhow::Result<Box<dyn Read + 'a>> {
+    let rdr = open_file(f)?;
+    let paired = cfg.input_mode() != InputMode::Single;
+    Ok(match cfg.input_format() {
+        InputFormat::Fastq => Box::new(rdr),
+        // Qualities are output with the standard Phred+33 encoding
+        InputFormat::Fasta => Box::new(FastaReader::new(rdr, cfg.fasta_qual() + 33)),
+        InputFormat::Sam => Box::new(SamReader::new(rdr, false, paired)),
+        InputFormat::Bam => Box::new(SamReader::new(rdr, true, paired)),
+    })
+}
+
+fn open_file(f: &Path) -> anyhow::Result<impl Read> {
+    if is_stdin(f) {
+        CompressIo::new().reader()
+    } else {
+        CompressIo::new().path(f).reader()
+    }
+    .with_context(|| format!("Could not open input file {f:?}"))
+}
+
+pub fn reader(
+    cfg: &Config,
+    rcv: Receiver<Buffer>,
+    snd: Sender<Buffer>,
+    views: Option<(Sender<ViewMsg>, &SampleProgress)>,
+) -> anyhow::Result<()> {
     let nr = cfg.readers();
     let mut error = None;
+    // Quality offset for the run (0 if not yet known)
+    let qual_offset = AtomicU8::new(0);
 
     thread::scope(|scope| {
         debug!("Setting up reader(s)");
@@ -122,18 +450,27 @@ pub fn reader(cfg: &Config, rcv: Receiver<Buffer>, snd: Sender<Buffer>) -> anyho
         let reader_handles: Vec<_> = (0..nr)
             .map(|ix| {
                 let recv_buf = rcv.clone();
-                let send_buf = snd.clone();
+                let send_buf = BufferSender {
+                    snd: snd.clone(),
+                    views: views.clone(),
+                };
                 let file_recv = file_recv.clone();
+                let qual_offset = &qual_offset;
 
-                scope.spawn(move || read_thread(cfg, ix, recv_buf, send_buf, file_recv))
+                scope.spawn(move || {
+                    read_thread(cfg, ix, recv_buf, send_buf, file_recv, qual_offset)
+                })
             })
             .collect();
 
         drop(file_recv);
 
-        // Send file indices to readers
-        let nf = cfg.input_files().len() >> 1;
-        for ix in 0..nf {
+        // Send file indices to readers.  Streams are sent first as their writers
+        // may be blocked until they are read; the remaining inputs are sent in order
+        // of decreasing size
+        let mut order: Vec<_> = cfg.inputs().iter().enumerate().collect();
+        order.sort_by_key(|(_, i)| std::cmp::Reverse(i.size().unwrap_or(u64::MAX)));
+        for (ix, _) in order {
             file_send.send(ix).expect("Error sending file index to readers")
         }
 
@@ -1,25 +1,108 @@
 use anyhow::Context;
-use std::io::Read;
+use memmap2::Mmap;
+use std::{io::Read, ops::Range, sync::Arc};
 
-use crate::process::FastQIter;
+use crate::{
+    cli::Config,
+    process::{
+        FastQIter,
+        fastq::{RecordIndex, record_span},
+    },
+};
 
-const BUF_SIZE: usize = 1048576;
+/// Result of filling a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FillStatus {
+    /// More input remains
+    More,
+    /// All inputs at EOF
+    Eof,
+    /// One input reached EOF while the other (given by index) still has records
+    Unequal(usize),
+}
+
+/// Location of the start of the buffer contents in the input file(s), used for error reporting
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+    /// Index of input
+    input: usize,
+    /// Index of first record in each file
+    rec: [u64; 2],
+    /// Distance between successive records in the file
+    rec_step: u64,
+    /// Line index and byte offset of the start of each buffer in the file (if contiguous
+    /// in the file)
+    line: [Option<u64>; 2],
+    offset: [Option<u64>; 2],
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self {
+            input: 0,
+            rec: [0; 2],
+            rec_step: 1,
+            line: [None; 2],
+            offset: [None; 2],
+        }
+    }
+}
 
+/// Records found in the inputs of a buffer (see [Buffer::check_records])
+struct Indexed {
+    /// Number of complete records common to the inputs
+    n: usize,
+    /// Byte offset and line index of the end of the common records in each input
+    end: [(usize, usize); 2],
+    /// Whether data remains in each input after its complete records
+    left: [bool; 2],
+    /// Input with more records if the other input is exhausted
+    unequal: Option<usize>,
+}
+
+/// Region of a memory mapped input file
+struct MappedSlice {
+    map: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+/// Pair of input buffers (one per read).  Each buffer starts at `size` bytes
+/// and can grow up to `max_size` bytes if required to hold a complete record, returning to
+/// `size` bytes when the buffer is cleared.
+/// Alternatively the buffers can refer to regions of memory mapped input files
 pub struct Buffer {
-    inner: [Box<[u8]>; 2],
+    inner: [Vec<u8>; 2],
+    mapped: [Option<MappedSlice>; 2],
     used: [usize; 2],
+    n_rec: usize,
+    n_lines: [usize; 2],
+    index: [RecordIndex; 2],
+    indexed: bool,
+    size: usize,
+    max_size: usize,
+    origin: Origin,
+    qual_offset: u8,
     ix: usize,
+    sample: usize,
 }
 
 impl Buffer {
-    pub fn new(ix: usize) -> Self {
-        let v1 = vec![0u8; BUF_SIZE].into_boxed_slice();
-        let v2 = vec![0u8; BUF_SIZE].into_boxed_slice();
-        let inner = [v1, v2];
+    pub fn new(ix: usize, size: usize, max_size: usize) -> Self {
+        let inner = [vec![0u8; size], vec![0u8; size]];
         Self {
             inner,
+            mapped: [None, None],
             used: [0; 2],
+            n_rec: 0,
+            n_lines: [0; 2],
+            index: [RecordIndex::default(), RecordIndex::default()],
+            indexed: false,
+            size,
+            max_size,
+            origin: Origin::default(),
+            qual_offset: 33,
             ix,
+            sample: 0,
         }
     }
 
@@ -27,43 +110,272 @@ impl Buffer {
         self.ix
     }
 
+    /// Index of the sample the buffer contents come from
+    pub fn sample(&self) -> usize {
+        self.sample
+    }
+
+    pub(super) fn set_sample(&mut self, sample: usize) {
+        self.sample = sample
+    }
+
+    /// Offset of base quality values
+    pub fn qual_offset(&self) -> u8 {
+        self.qual_offset
+    }
+
+    pub(super) fn set_qual_offset(&mut self, q: u8) {
+        self.qual_offset = q
+    }
+
+    /// Set origin for buffers filled from separate files, where the buffer contents
+    /// start at record `rec`, lines `line` and byte offsets `offset` in the files
+    pub(super) fn set_origin(&mut self, input: usize, rec: u64, line: [u64; 2], offset: [u64; 2]) {
+        self.origin = Origin {
+            input,
+            rec: [rec; 2],
+            rec_step: 1,
+            line: line.map(Some),
+            offset: offset.map(Some),
+        }
+    }
+
+    /// Set origin for buffers filled from an interleaved file, starting at record pair `pair`
+    pub(super) fn set_origin_interleaved(&mut self, input: usize, pair: u64) {
+        self.origin = Origin {
+            input,
+            rec: [pair << 1, (pair << 1) + 1],
+            rec_step: 2,
+            line: [None; 2],
+            offset: [None; 2],
+        }
+    }
+
     pub fn used(&self) -> &[usize] {
         &self.used
     }
 
+    /// Number of records (or record pairs) in the buffer
+    pub(super) fn n_records(&self) -> usize {
+        self.n_rec
+    }
+
+    /// Number of lines in each buffer (only set by `fill`)
+    pub(super) fn n_lines(&self) -> &[usize; 2] {
+        &self.n_lines
+    }
+
     /// Fills buffer unless EOF or an error occurs
-    /// If buffer is full, returns a slice containing the possibly incomplete last entry,
-    /// otherwise it returns an empty slice
+    /// The possibly incomplete last entry is moved to `rem`
+    /// For single-end input `rdr` has one element and only the first buffer is used
+    ///
+    /// For paired input, if one input is exhausted while the other still has records,
+    /// returns `FillStatus::Unequal` with the index of the longer input.  In this case the
+    /// buffer contains the common records, and the unmatched records are left in `rem`
     pub(super) fn fill<R: Read>(
         &mut self,
-        rdr: [&mut R; 2],
+        rdr: &mut [R],
         rem: &mut Self,
-    ) -> anyhow::Result<bool> {
-        // Fill both individual buffers
-        let eof1 = self.fill_single_buf(rdr[0], 0)?;
-        let eof2 = self.fill_single_buf(rdr[1], 1)?;
-        
-        // Step through records to find the last Ipossibly incomplete) record common to both buffers
-        if let Some((ix1, ix2)) = self.inner[0][..self.used[0]]
-            .split(|c| *c == b'\n')
-            .zip(self.inner[1][..self.used[1]].split(|c| *c == b'\n'))
-            .step_by(4)
-            .last()
-            .map(|(s1, s2)| {
-                (
-                    s1.as_ptr().addr() - self.inner[0].as_ptr().addr(),
-                    s2.as_ptr().addr() - self.inner[1].as_ptr().addr(),
-                )
-            })
+    ) -> anyhow::Result<FillStatus> {
+        let nr = rdr.len();
+        let mut eof = [true; 2];
+        let Indexed { n, end: [(ix1, _), (ix2, _)], left, unequal } = loop {
+            // Fill individual buffers
+            for (ix, r) in rdr.iter_mut().enumerate() {
+                eof[ix] = self.fill_single_buf(r, ix)?;
+            }
+
+            // Index records to find the last (possibly incomplete) record common to both buffers
+            for (ix, idx) in self.index.iter_mut().enumerate() {
+                if ix < nr {
+                    idx.build(&self.inner[ix][..self.used[ix]], eof[ix])
+                } else {
+                    idx.clear()
+                }
+            }
+            let (s1, s2) = self.as_slices();
+            let x = self.check_records([s1, s2], &eof[..nr])?;
+
+            if x.n > 0 || x.unequal.is_some() || eof[..nr].iter().all(|x| *x) {
+                self.n_lines = x.end.map(|(_, l)| l);
+                break x;
+            }
+
+            // No complete record - grow the full buffer(s) and read more
+            let mut grown = false;
+            for (i, _) in eof[..nr].iter().enumerate().filter(|(_, e)| !**e) {
+                grown |= self.grow(i)
+            }
+            if !grown {
+                return Err(anyhow!(
+                    "FASTQ record larger than maximum buffer size ({} bytes)",
+                    self.max_size
+                ));
+            }
+        };
+
+        self.n_rec = n;
+        for idx in self.index[..nr].iter_mut() {
+            idx.truncate(n)
+        }
+        self.indexed = true;
+        self.set_used_and_rem(ix1, 0, rem);
+        self.set_used_and_rem(ix2, 1, rem);
+        let eof = eof[..nr].iter().all(|x| *x);
+        // At EOF the remainder can only contain an incomplete record
+        rem.n_rec = usize::from(eof && left.iter().any(|x| *x));
+
+        Ok(match unequal {
+            Some(i) => FillStatus::Unequal(i),
+            None if eof => FillStatus::Eof,
+            None => FillStatus::More,
+        })
+    }
+
+    /// Fills buffer with regions of the memory mapped input files `maps`, starting at `pos`
+    /// in each file.  No data is copied: the records are indexed in place, and `pos` is
+    /// advanced past the records in the buffer.  Return values are as for [Buffer::fill]; at
+    /// EOF a final record without a terminating newline is included in the buffer
+    pub(super) fn fill_mapped(
+        &mut self,
+        maps: &[Arc<Mmap>],
+        pos: &mut [usize; 2],
+    ) -> anyhow::Result<FillStatus> {
+        let nr = maps.len();
+        let mut size = self.inner[0].len();
+        loop {
+            let mut end = [0; 2];
+            let mut eof = [true; 2];
+            for (ix, idx) in self.index.iter_mut().enumerate() {
+                match maps.get(ix) {
+                    Some(m) => {
+                        end[ix] = (pos[ix] + size).min(m.len());
+                        eof[ix] = end[ix] == m.len();
+                        idx.build(&m[pos[ix]..end[ix]], eof[ix])
+                    }
+                    None => idx.clear(),
+                }
+            }
+            let s = [0, 1].map(|i| maps.get(i).map(|m| &m[pos[i]..end[i]]).unwrap_or(&[]));
+            let Indexed { n, end: [(ix1, l1), (ix2, l2)], left, unequal } =
+                self.check_records(s, &eof[..nr])?;
+            let start = [pos[0] + ix1, pos[1] + ix2];
+            let eof = eof[..nr].iter().all(|x| *x);
+
+            if n > 0 || unequal.is_some() || eof {
+                let last = eof && unequal.is_none();
+                for (i, m) in maps.iter().enumerate() {
+                    let e = if last { end[i] } else { start[i] };
+                    self.mapped[i] = Some(MappedSlice {
+                        map: Arc::clone(m),
+                        range: pos[i]..e,
+                    });
+                    pos[i] = e;
+                }
+                for idx in self.index[..nr].iter_mut() {
+                    idx.truncate(n)
+                }
+                self.indexed = true;
+                self.n_lines = [l1, l2];
+                self.n_rec = n + usize::from(last && left.iter().any(|x| *x));
+                return Ok(match unequal {
+                    Some(i) => FillStatus::Unequal(i),
+                    None if eof => FillStatus::Eof,
+                    None => FillStatus::More,
+                });
+            }
+
+            // No complete record - extend the region searched
+            if size >= self.max_size {
+                return Err(anyhow!(
+                    "FASTQ record larger than maximum buffer size ({} bytes)",
+                    self.max_size
+                ));
+            }
+            size = (size << 1).clamp(1, self.max_size)
+        }
+    }
+
+    /// Find the records common to the indexed inputs with data `s`, where `eof` gives the
+    /// EOF status of the inputs in use.  An input is taken as having fewer records than the
+    /// other if it is exhausted and has fewer complete records (any remaining data can only be
+    /// an incomplete record), or if it is exhausted with no remaining data while the other
+    /// input has data and more to read.  Returns an error if an exhausted input has no
+    /// complete records but ends in an incomplete record, as reading more can not help
+    fn check_records(&self, s: [&[u8]; 2], eof: &[bool]) -> anyhow::Result<Indexed> {
+        let nr = eof.len();
+        let idx = &self.index[..nr];
+        let counts = [0, 1].map(|i| idx.get(i).map(|x| x.n_records()).unwrap_or(0));
+        let n = counts[..nr].iter().copied().min().unwrap_or(0);
+        let end = [0, 1].map(|i| idx.get(i).map(|x| x.rec_start(n)).unwrap_or((0, 0)));
+        let has_data = |s: &[u8]| s.iter().any(|c| !c.is_ascii_whitespace());
+        let left = [0, 1].map(|i| {
+            idx.get(i)
+                .map(|x| has_data(&s[i][x.rec_start(counts[i]).0..]))
+                .unwrap_or(false)
+        });
+        let unequal = if nr == 1 {
+            None
+        } else {
+            [(0, 1), (1, 0)]
+                .into_iter()
+                .find(|&(i, j)| {
+                    eof[i]
+                        && (counts[i] < counts[j]
+                            || (counts[i] == counts[j] && !left[i] && left[j] && !eof[j]))
+                })
+                .map(|(_, j)| j)
+        };
+        if n == 0
+            && unequal.is_none()
+            && !eof.iter().all(|x| *x)
+            && let Some(i) = (0..nr).find(|&i| eof[i] && left[i] && counts[i] == 0)
         {
-            self.set_used_and_rem(ix1, 0, rem);
-            self.set_used_and_rem(ix2, 1, rem);
-            Ok(eof1 && eof2)
+            return Err(anyhow!("Incomplete FASTQ record at end of read {} input", i + 1));
+        }
+        Ok(Indexed {
+            n,
+            end,
+            left,
+            unequal,
+        })
+    }
+
+    /// Increases the size of buffer `i` (doubling, up to the maximum size).
+    /// Returns false if the buffer is already at the maximum size
+    pub(super) fn grow(&mut self, i: usize) -> bool {
+        let l = self.inner[i].len();
+        if l >= self.max_size {
+            false
         } else {
-            Err(anyhow!("Buffer to small for complete FASTQ record"))
+            let new_size = (l << 1).clamp(1, self.max_size);
+            trace!(
+                "Growing buffer {} ({i}) from {l} to {new_size} bytes",
+                self.ix
+            );
+            self.inner[i].resize(new_size, 0);
+            true
         }
     }
 
+    /// Appends a pair of complete FASTQ records, adding a terminating newline if missing.
+    /// Returns false (and leaves the buffer unchanged) if there is not enough space
+    pub(super) fn push_pair(&mut self, r1: &[u8], r2: &[u8]) -> bool {
+        let needed = |r: &[u8]| r.len() + usize::from(r.last() != Some(&b'\n'));
+        let (l1, l2) = (needed(r1), needed(r2));
+        if self.used[0] + l1 > self.inner[0].len() || self.used[1] + l2 > self.inner[1].len() {
+            return false;
+        }
+        for (i, (r, l)) in [(r1, l1), (r2, l2)].into_iter().enumerate() {
+            let b = &mut self.inner[i][self.used[i]..self.used[i] + l];
+            b[..r.len()].copy_from_slice(r);
+            b[l - 1] = b'\n';
+            self.used[i] += l;
+        }
+        self.n_rec += 1;
+        true
+    }
+
     /// Fills buffer from rdr
     /// Returns true at EOF
     fn fill_single_buf<R: Read>(&mut self, rdr: &mut R, ix: usize) -> anyhow::Result<bool> {
@@ -84,8 +396,11 @@ impl Buffer {
     }
 
     fn set_used_and_rem(&mut self, ix: usize, i: usize, rem: &mut Self) {
+        let l = self.used[i] - ix;
+        if rem.inner[i].len() < l {
+            rem.inner[i].resize(self.inner[i].len(), 0)
+        }
         let r = &self.inner[i][ix..self.used[i]];
-        let l = r.len();
         self.used[i] = ix;
         rem.inner[i][..l].copy_from_slice(r);
         rem.used[i] = l
@@ -93,27 +408,247 @@ impl Buffer {
 
     #[inline]
     pub fn as_slices(&self) -> (&[u8], &[u8]) {
-        (
-            &self.inner[0][..self.used[0]],
-            &self.inner[1][..self.used[1]],
-        )
+        (self.as_slices_ix(0), self.as_slices_ix(1))
     }
 
+    /// Used part of one buffer
     #[inline]
-    pub fn fastq<'a>(&'a self) -> (FastQIter<'a>, FastQIter<'a>) {
-        let (s1, s2) = self.as_slices();
+    pub(super) fn as_slices_ix(&self, i: usize) -> &[u8] {
+        match &self.mapped[i] {
+            Some(m) => &m.map[m.range.clone()],
+            None => &self.inner[i][..self.used[i]],
+        }
+    }
 
-        (FastQIter::make(s1), FastQIter::make(s2))
+    /// Iterators over the FASTQ records in the buffer, with the location of the records in
+    /// the input files for error reporting.  The record index is used if it was built
+    /// when the buffer was filled
+    #[inline]
+    pub fn fastq<'a>(&'a self, cfg: &'a Config) -> (FastQIter<'a>, FastQIter<'a>) {
+        let (s1, s2) = self.as_slices();
+        let mk = |s, i: usize| {
+            if self.indexed {
+                FastQIter::make_indexed(s, &self.index[i])
+            } else {
+                FastQIter::make(s)
+            }
+        };
+        let o = &self.origin;
+        let mut files = cfg.inputs()[o.input].files();
+        let f1 = files.next().expect("Missing input file");
+        // Interleaved input has both reads in the same file
+        let f2 = files.next().unwrap_or(f1);
+        (
+            mk(s1, 0).with_origin(f1, o.rec[0], o.rec_step, o.line[0], o.offset[0]),
+            mk(s2, 1).with_origin(f2, o.rec[1], o.rec_step, o.line[1], o.offset[1]),
+        )
     }
 
     #[inline]
     pub fn clear(&mut self) {
         self.used[0] = 0;
         self.used[1] = 0;
+        self.mapped = [None, None];
+        self.n_rec = 0;
+        self.n_lines = [0; 2];
+        self.indexed = false;
+        for idx in self.index.iter_mut() {
+            idx.clear()
+        }
+        // Release memory from buffers that were grown to fit long records
+        for v in self.inner.iter_mut().filter(|v| v.len() > self.size) {
+            trace!(
+                "Shrinking buffer {} from {} to {} bytes",
+                self.ix,
+                v.len(),
+                self.size
+            );
+            v.truncate(self.size);
+            v.shrink_to_fit()
+        }
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.used[0] + self.used[1] == 0
+        let (s1, s2) = self.as_slices();
+        s1.is_empty() && s2.is_empty()
+    }
+}
+
+/// Number of records in `s` followed by the remaining input in `rdr`.  A final incomplete
+/// record is also counted
+pub(super) fn count_records<R: Read>(s: &[u8], rdr: &mut R) -> anyhow::Result<usize> {
+    // Count the complete records in `s` in place (it can be a large region of a memory mapped
+    // file), so that only the incomplete remainder is copied
+    let mut n = 0;
+    let mut p = 0;
+    while let Some(r) = record_span(&s[p..], false) {
+        n += 1;
+        p += r.len
+    }
+    let mut v = s[p..].to_vec();
+    let mut b = vec![0u8; 65536];
+    loop {
+        let l = rdr
+            .read(&mut b)
+            .with_context(|| "Error reading from input")?;
+        let eof = l == 0;
+        v.extend_from_slice(&b[..l]);
+        let mut p = 0;
+        while let Some(r) = record_span(&v[p..], eof) {
+            n += 1;
+            p += r.len
+        }
+        v.drain(..p);
+        if eof {
+            break;
+        }
+    }
+    if v.iter().any(|c| !c.is_ascii_whitespace()) {
+        n += 1
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(r: std::ops::Range<usize>) -> Vec<u8> {
+        r.flat_map(|i| format!("@r{i}\nACGT\n+\nIIII\n").into_bytes())
+            .collect()
+    }
+
+    fn fill_pair(r1: &[u8], r2: &[u8], size: usize) -> anyhow::Result<(Buffer, FillStatus)> {
+        let mut b = Buffer::new(0, size, size << 4);
+        let mut rem = Buffer::new(1, size, size << 4);
+        let status = b.fill(&mut [r1, r2], &mut rem)?;
+        Ok((b, status))
+    }
+
+    #[test]
+    fn equal_records() {
+        let r = records(0..3);
+        let (b, status) = fill_pair(&r, &r, 256).unwrap();
+        assert_eq!(status, FillStatus::Eof);
+        assert_eq!(b.n_records(), 3);
+    }
+
+    #[test]
+    fn unequal_records() {
+        let (r1, r2) = (records(0..3), records(0..2));
+        let (b, status) = fill_pair(&r1, &r2, 256).unwrap();
+        assert_eq!(status, FillStatus::Unequal(0));
+        assert_eq!(b.n_records(), 2);
+
+        // Read 2 exhausted while read 1 still has more to read
+        let (b, status) = fill_pair(&records(0..20), &[], 64).unwrap();
+        assert_eq!(status, FillStatus::Unequal(0));
+        assert_eq!(b.n_records(), 0);
+    }
+
+    #[test]
+    fn incomplete_record_at_eof() {
+        let r1 = b"@r0\nACGT\n+\nII";
+        let r2 = b"@r0\nACGTACGTACGT\n+\nIIIIIIIIIIII\n";
+        let Err(e) = fill_pair(r1, r2, 16) else {
+            panic!("Incomplete record not detected")
+        };
+        assert!(
+            e.to_string()
+                .contains("Incomplete FASTQ record at end of read 1")
+        );
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let r = b"@r0\nACGTACGTACGTACGTACGT\n+\nIIIIIIIIIIIIIIIIIIII\n";
+        let mut b = Buffer::new(0, 16, 64);
+        let mut rem = Buffer::new(1, 16, 64);
+        let status = b.fill(&mut [&r[..]], &mut rem).unwrap();
+        assert_eq!(status, FillStatus::Eof);
+        assert_eq!(b.n_records(), 1);
+        assert_eq!(b.inner[0].len(), 64);
+        b.clear();
+        assert_eq!(b.inner[0].len(), 16);
+
+        // Record does not fit in the maximum buffer size
+        let mut b = Buffer::new(0, 16, 32);
+        let Err(e) = b.fill(&mut [&r[..]], &mut rem) else {
+            panic!("Oversized record not detected")
+        };
+        assert!(e.to_string().contains("larger than maximum buffer size"));
+    }
+
+    /// Contents, record count and line counts of the buffers filled from the input
+    type Filled = (Vec<u8>, Vec<u8>, usize, [usize; 2]);
+
+    fn add_buffer(out: &mut Filled, b: &Buffer) {
+        let (s1, s2) = b.as_slices();
+        out.0.extend_from_slice(s1);
+        out.1.extend_from_slice(s2);
+        out.2 += b.n_records();
+        for (l, n) in out.3.iter_mut().zip(b.n_lines()) {
+            *l += n
+        }
+    }
+
+    #[test]
+    fn mapped_and_streamed_inputs_match() {
+        // Records of varying length, with no newline at the end of the files
+        let mk = |r: usize| {
+            let mut v: Vec<u8> = (0..40)
+                .flat_map(|i| {
+                    let s = "ACGT".repeat(1 + (i * 7 + r) % 11);
+                    format!("@r{i}/{r}\n{s}\n+\n{}\n", "I".repeat(s.len())).into_bytes()
+                })
+                .collect();
+            v.pop();
+            v
+        };
+        let data = [mk(1), mk(2)];
+        let (size, max_size) = (64, 1024);
+
+        let mut streamed = Filled::default();
+        let mut rdr = [&data[0][..], &data[1][..]];
+        let mut b = Buffer::new(0, size, max_size);
+        loop {
+            let mut rem = Buffer::new(1, size, max_size);
+            let status = b.fill(&mut rdr, &mut rem).unwrap();
+            add_buffer(&mut streamed, &b);
+            if status == FillStatus::Eof {
+                assert!(rem.is_empty());
+                break;
+            }
+            b = rem;
+        }
+
+        let maps = [0, 1].map(|i| {
+            let path = std::env::temp_dir().join(format!(
+                "ampl_seq_test_{}_{}.fq",
+                std::process::id(),
+                i + 1
+            ));
+            std::fs::write(&path, &data[i]).unwrap();
+            let file = std::fs::File::open(&path).unwrap();
+            // SAFETY: the file is not modified while it is mapped
+            let map = unsafe { Mmap::map(&file) }.unwrap();
+            std::fs::remove_file(&path).unwrap();
+            Arc::new(map)
+        });
+        let mut mapped = Filled::default();
+        let mut pos = [0; 2];
+        loop {
+            let mut b = Buffer::new(0, size, max_size);
+            let status = b.fill_mapped(&maps, &mut pos).unwrap();
+            add_buffer(&mut mapped, &b);
+            if status == FillStatus::Eof {
+                break;
+            }
+        }
+
+        assert_eq!(streamed.2, 40);
+        assert_eq!(streamed, mapped);
+        assert_eq!(mapped.0, data[0]);
     }
 }
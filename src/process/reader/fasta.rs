@@ -0,0 +1,120 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::process::fastq::trim_cr;
+
+// Minimum amount of output to generate per call to fill_out()
+const OUT_CHUNK: usize = 65536;
+
+/// Converts FASTA to FASTQ, giving every base the same quality.
+///
+/// Sequences can be on a single line or split over multiple lines, and lines can be terminated
+/// with `\r\n`.  Blank lines and comment lines (starting with ';') are skipped.
+pub(super) struct FastaReader<R> {
+    rdr: BufReader<R>,
+    /// Quality value (as an ASCII character) given to all bases
+    qual: u8,
+    line: Vec<u8>,
+    /// Current record (header without the '>', and sequence)
+    name: Option<Vec<u8>>,
+    seq: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> FastaReader<R> {
+    pub(super) fn new(rdr: R, qual: u8) -> Self {
+        Self {
+            rdr: BufReader::new(rdr),
+            qual,
+            line: Vec::new(),
+            name: None,
+            seq: Vec::new(),
+            out: Vec::with_capacity(OUT_CHUNK << 1),
+            pos: 0,
+        }
+    }
+
+    /// Generate more output.  Returns false at EOF
+    fn fill_out(&mut self) -> io::Result<bool> {
+        self.out.clear();
+        self.pos = 0;
+        while self.out.len() < OUT_CHUNK {
+            self.line.clear();
+            if self.rdr.read_until(b'\n', &mut self.line)? == 0 {
+                self.write_fastq();
+                break;
+            }
+            let l = trim_cr(self.line.strip_suffix(b"\n").unwrap_or(&self.line));
+            match l.first() {
+                None | Some(b';') => {}
+                Some(b'>') => {
+                    let name = l[1..].to_vec();
+                    self.write_fastq();
+                    self.name = Some(name);
+                }
+                Some(_) if self.name.is_none() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Missing '>' at start of FASTA input",
+                    ));
+                }
+                Some(_) => self.seq.extend_from_slice(l),
+            }
+        }
+        Ok(!self.out.is_empty())
+    }
+
+    /// Write current record (if any) to the output as FASTQ
+    fn write_fastq(&mut self) {
+        if let Some(name) = self.name.take() {
+            let out = &mut self.out;
+            out.push(b'@');
+            out.extend_from_slice(&name);
+            out.push(b'\n');
+            out.extend_from_slice(&self.seq);
+            out.extend_from_slice(b"\n+\n");
+            out.resize(out.len() + self.seq.len(), self.qual);
+            out.push(b'\n');
+            self.seq.clear();
+        }
+    }
+}
+
+impl<R: Read> Read for FastaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() && !self.fill_out()? {
+            return Ok(0);
+        }
+        let l = buf.len().min(self.out.len() - self.pos);
+        buf[..l].copy_from_slice(&self.out[self.pos..self.pos + l]);
+        self.pos += l;
+        Ok(l)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(s: &[u8]) -> io::Result<Vec<u8>> {
+        let mut v = Vec::new();
+        FastaReader::new(s, 30 + 33).read_to_end(&mut v)?;
+        Ok(v)
+    }
+
+    #[test]
+    fn fasta_to_fastq() {
+        let s = b";comment\n>r1 desc\nACGT\nAC\r\n\n>r2\r\nGGT\n>r3\nTTAA";
+        assert_eq!(
+            convert(s).unwrap(),
+            b"@r1 desc\nACGTAC\n+\n??????\n@r2\nGGT\n+\n???\n@r3\nTTAA\n+\n????\n"
+        );
+        assert!(convert(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_header() {
+        let e = convert(b"ACGT\n>r1\nACGT\n").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
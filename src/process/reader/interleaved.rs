@@ -0,0 +1,146 @@
+use std::io::Read;
+
+use anyhow::Context;
+
+use super::Buffer;
+use crate::{
+    cli::Pairing,
+    process::{
+        fastq::record_span,
+        read_id::{IdMatch, first_token, match_ids},
+    },
+};
+
+/// Reader for interleaved FASTQ, where records from read 1 and read 2 alternate.
+/// Input is read into a staging buffer, and record pairs are then split between
+/// the two halves of a [Buffer].  The staging buffer grows as required up to `max_size`
+pub(super) struct Interleaved<R> {
+    rdr: R,
+    stage: Vec<u8>,
+    max_size: usize,
+    pairing: Pairing,
+    start: usize,
+    end: usize,
+    eof: bool,
+    n_pairs: usize,
+}
+
+impl<R: Read> Interleaved<R> {
+    pub(super) fn new(rdr: R, size: usize, max_size: usize, pairing: Pairing) -> Self {
+        Self {
+            rdr,
+            stage: vec![0u8; size],
+            max_size,
+            pairing,
+            start: 0,
+            end: 0,
+            eof: false,
+            n_pairs: 0,
+        }
+    }
+
+    /// Number of record pairs read so far
+    pub(super) fn n_pairs(&self) -> usize {
+        self.n_pairs
+    }
+
+    /// Fills buffer with record pairs until the buffer is full or EOF is reached
+    /// Returns true at EOF
+    pub(super) fn fill(&mut self, b: &mut Buffer) -> anyhow::Result<bool> {
+        loop {
+            let s = &self.stage[self.start..self.end];
+            let pair = record_len(s, self.eof)
+                .and_then(|l1| record_len(&s[l1..], self.eof).map(|l2| (l1, l2)));
+
+            match pair {
+                Some((l1, l2)) => {
+                    let (r1, r2) = (&s[..l1], &s[l1..l1 + l2]);
+                    check_ids(self.pairing, r1, r2)
+                        .with_context(|| format!("Record pair {}", self.n_pairs + 1))?;
+                    if !b.push_pair(r1, r2) {
+                        if !b.is_empty() {
+                            return Ok(false);
+                        }
+                        // Record pair does not fit in empty buffer, so try to make it larger
+                        if !(b.grow(0) | b.grow(1)) {
+                            return Err(anyhow!(
+                                "FASTQ record pair larger than maximum buffer size"
+                            ));
+                        }
+                        continue;
+                    }
+                    self.start += l1 + l2;
+                    self.n_pairs += 1;
+                }
+                None if self.eof => {
+                    return if self.start < self.end {
+                        Err(anyhow!(
+                            "Incomplete record pair at end of input (after {} pairs)",
+                            self.n_pairs
+                        ))
+                    } else {
+                        Ok(true)
+                    };
+                }
+                None => self.refill()?,
+            }
+        }
+    }
+
+    /// Move unused data to start of staging buffer and read more input
+    fn refill(&mut self) -> anyhow::Result<()> {
+        if self.start == 0 && self.end == self.stage.len() {
+            let l = self.stage.len();
+            if l >= self.max_size {
+                return Err(anyhow!(
+                    "FASTQ record pair larger than maximum staging buffer size ({} bytes)",
+                    self.max_size
+                ));
+            }
+            self.stage.resize((l << 1).min(self.max_size), 0);
+        }
+        self.stage.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        while self.end < self.stage.len() {
+            let l = self
+                .rdr
+                .read(&mut self.stage[self.end..])
+                .with_context(|| "Error reading from input")?;
+            if l == 0 {
+                self.eof = true;
+                break;
+            }
+            self.end += l;
+        }
+        Ok(())
+    }
+}
+
+/// Length of the first FASTQ record in `s`, including the trailing newline.
+/// Returns None if `s` does not contain a complete record.  At EOF, the last line
+/// does not need to be terminated by a newline
+fn record_len(s: &[u8], eof: bool) -> Option<usize> {
+    record_span(s, eof).map(|r| r.len)
+}
+
+/// Read ID (header line without the leading '@')
+fn read_id(r: &[u8]) -> &[u8] {
+    let r = r.strip_prefix(b"@").unwrap_or(r);
+    let id = r.split(|c| *c == b'\n').next().unwrap_or_default();
+    id.strip_suffix(b"\r").unwrap_or(id)
+}
+
+/// Check that the read IDs of a record pair match
+fn check_ids(pairing: Pairing, r1: &[u8], r2: &[u8]) -> anyhow::Result<()> {
+    let (id1, id2) = (read_id(r1), read_id(r2));
+    if match_ids(pairing, id1, id2) != IdMatch::Mismatch {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Mismatch between IDs of interleaved read 1 and read 2 ({} / {})",
+            String::from_utf8_lossy(first_token(id1)),
+            String::from_utf8_lossy(first_token(id2))
+        ))
+    }
+}
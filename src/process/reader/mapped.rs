@@ -0,0 +1,53 @@
+use std::{fs::File, path::Path, sync::Arc};
+
+use anyhow::Context;
+use memmap2::Mmap;
+
+use crate::cli::{Config, InputFormat, InputMode, is_stdin};
+
+/// Memory map the input files if this was requested and the input consists of regular,
+/// uncompressed FASTQ files (paired or single end).  Returns None if the input should be
+/// read in the normal way
+pub(super) fn map_input(cfg: &Config, files: &[&Path]) -> anyhow::Result<Option<Vec<Arc<Mmap>>>> {
+    if !cfg.mmap()
+        || cfg.input_format() != InputFormat::Fastq
+        || cfg.input_mode() == InputMode::Interleaved
+    {
+        return Ok(None);
+    }
+    let mut maps = Vec::with_capacity(files.len());
+    for f in files {
+        match map_file(f)? {
+            Some(m) => maps.push(Arc::new(m)),
+            None => {
+                debug!("Input file {f:?} can not be memory mapped");
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(maps))
+}
+
+/// Memory map a regular file if it looks like uncompressed FASTQ (i.e., the first
+/// non-whitespace character is '@').  Other files (including compressed files) are not mapped
+fn map_file(f: &Path) -> anyhow::Result<Option<Mmap>> {
+    if is_stdin(f) {
+        return Ok(None);
+    }
+    let file = File::open(f).with_context(|| format!("Could not open input file {f:?}"))?;
+    if !file
+        .metadata()
+        .with_context(|| format!("Could not get metadata for input file {f:?}"))?
+        .is_file()
+    {
+        return Ok(None);
+    }
+    // SAFETY: the mapping is read only, and input files are not expected to be modified
+    // while they are being read
+    let map = unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Could not memory map input file {f:?}"))?;
+    Ok(match map.iter().find(|c| !c.is_ascii_whitespace()) {
+        Some(b'@') | None => Some(map),
+        Some(_) => None,
+    })
+}
@@ -0,0 +1,82 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use super::Buffer;
+use crate::{
+    cli::{Config, InputFormat},
+    process::FastQIter,
+};
+
+/// Number of records sampled from each file to detect the quality encoding
+const N_SAMPLE: usize = 1000;
+
+/// Determine the quality offset for an input, checking that it is consistent with the other
+/// inputs in the run.  Unless given explicitly, the offset is detected from the first records
+/// in `b`.  `run_offset` holds the offset of the first input checked (0 if not yet set)
+pub(super) fn input_qual_offset(
+    cfg: &Config,
+    b: &Buffer,
+    files: &[&Path],
+    run_offset: &AtomicU8,
+) -> anyhow::Result<u8> {
+    if cfg.input_format() != InputFormat::Fastq {
+        // Converted inputs (FASTA/SAM/BAM) always have Phred+33 qualities
+        return Ok(33);
+    } else if let Some(q) = cfg.qual_offset() {
+        return Ok(q);
+    }
+
+    let (s1, s2) = b.as_slices();
+    let q1 = detect(s1);
+    let q = match (q1, detect(s2)) {
+        (Some(x), Some(y)) if x != y => {
+            return Err(anyhow!(
+                "Input files {:?} and {:?} have different quality encodings (Phred+{x} and Phred+{y})",
+                files[0],
+                files[files.len() - 1]
+            ));
+        }
+        (Some(x), _) | (None, Some(x)) => x,
+        // Empty input
+        (None, None) => return Ok(33),
+    };
+    debug!("Input {files:?}: detected quality encoding Phred+{q}");
+
+    match run_offset.compare_exchange(0, q, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {
+            info!("Quality encoding Phred+{q} detected");
+            Ok(q)
+        }
+        Err(x) if x == q => Ok(q),
+        Err(x) => Err(anyhow!(
+            "Mixed quality encodings: input {files:?} is Phred+{q}, but other inputs are Phred+{x} (use --qual-offset to override)"
+        )),
+    }
+}
+
+/// Detect quality offset from the first records in `s`.  Phred+64 is assumed if no
+/// quality values below '@' are found and there are values above 'J' (the highest value
+/// normally found with Phred+33).  If all values are in '@'-'J', either encoding is possible
+/// and Phred+33 (the modern standard) is assumed.  Returns None if there are no complete records
+fn detect(s: &[u8]) -> Option<u8> {
+    let (min, max) = FastQIter::make(s)
+        .take(N_SAMPLE)
+        .map_while(Result::ok)
+        .fold(None, |mm, r| {
+            r.qual().iter().fold(mm, |mm, &q| match mm {
+                None => Some((q, q)),
+                Some((a, b)) => Some((q.min(a), q.max(b))),
+            })
+        })?;
+    if min < 64 {
+        Some(33)
+    } else if max <= b'J' {
+        // Qualities in @-J are valid in both encodings
+        warn!("Quality encoding is ambiguous: assuming Phred+33 (use --qual-offset to override)");
+        Some(33)
+    } else {
+        Some(64)
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Read},
+    thread::Scope,
+};
+
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
+
+const CHUNK_SIZE: usize = 1048576;
+const N_CHUNKS: usize = 4;
+
+/// Reader where the input is opened and read by a separate thread, which keeps up to
+/// [N_CHUNKS] chunks of data ready for the consumer.  This means that inputs are consumed
+/// independently of each other, which is required for pipes where a single writer is
+/// supplying more than one input, and allows the two files of a pair to be decompressed
+/// in parallel.
+pub(super) struct ReadAhead {
+    rx: Receiver<anyhow::Result<Vec<u8>>>,
+    recycle: Sender<Vec<u8>>,
+    cur: Vec<u8>,
+    pos: usize,
+}
+
+impl ReadAhead {
+    /// Spawn thread in `scope` that opens the input using `open` and then reads from it
+    pub(super) fn spawn<'scope, 'env, F, R>(scope: &'scope Scope<'scope, 'env>, open: F) -> Self
+    where
+        F: FnOnce() -> anyhow::Result<R> + Send + 'scope,
+        R: Read,
+    {
+        let (tx, rx) = bounded(N_CHUNKS);
+        let (recycle, recycle_rx) = unbounded();
+        scope.spawn(move || read_ahead_thread(open, tx, recycle_rx));
+        Self {
+            rx,
+            recycle,
+            cur: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.cur.len() {
+            let v = match self.rx.recv() {
+                Ok(Ok(v)) => v,
+                Ok(Err(e)) => return Err(io::Error::other(e)),
+                // Reader thread has finished
+                Err(_) => return Ok(0),
+            };
+            // Ignore errors if reader thread has finished
+            let _ = self.recycle.send(std::mem::replace(&mut self.cur, v));
+            self.pos = 0;
+        }
+        let l = buf.len().min(self.cur.len() - self.pos);
+        buf[..l].copy_from_slice(&self.cur[self.pos..self.pos + l]);
+        self.pos += l;
+        Ok(l)
+    }
+}
+
+fn read_ahead_thread<F, R>(open: F, tx: Sender<anyhow::Result<Vec<u8>>>, recycle: Receiver<Vec<u8>>)
+where
+    F: FnOnce() -> anyhow::Result<R>,
+    R: Read,
+{
+    let mut rdr = match open() {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = tx.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        let mut v = recycle.try_recv().unwrap_or_default();
+        v.resize(CHUNK_SIZE, 0);
+        let mut n = 0;
+        let mut eof = false;
+        while n < CHUNK_SIZE {
+            match rdr.read(&mut v[n..]) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(l) => n += l,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let _ = tx.send(Err(anyhow!(e).context("Error reading from input")));
+                    return;
+                }
+            }
+        }
+        v.truncate(n);
+        // Stop if the consumer has gone away
+        if (n > 0 && tx.send(Ok(v)).is_err()) || eof {
+            break;
+        }
+    }
+}
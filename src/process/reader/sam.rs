@@ -0,0 +1,303 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::cli::iupac_complement;
+
+// SAM flags
+const FLAG_PAIRED: u16 = 0x1;
+const FLAG_REVERSE: u16 = 0x10;
+const FLAG_READ1: u16 = 0x40;
+const FLAG_READ2: u16 = 0x80;
+const FLAG_SECONDARY: u16 = 0x100;
+const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+// Minimum amount of output to generate per call to fill_out()
+const OUT_CHUNK: usize = 65536;
+
+const BAM_BASES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// Single decoded read
+#[derive(Default)]
+struct SamRec {
+    name: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Vec<u8>,
+    flag: u16,
+}
+
+/// Converts unaligned SAM or BAM to FASTQ.
+///
+/// If `paired` is set, the output is interleaved FASTQ with read 1 followed by read 2, using the
+/// READ1/READ2 flags to identify the mates, which must be adjacent in the input.  Otherwise each
+/// primary record is output as a separate read.  Secondary and supplementary records are skipped.
+/// Records flagged as reverse complemented are converted back to their original orientation.
+///
+/// BAM input should be already decompressed.
+pub(super) struct SamReader<R> {
+    rdr: BufReader<R>,
+    bam: bool,
+    paired: bool,
+    header_read: bool,
+    line: String,
+    raw: Vec<u8>,
+    rec: SamRec,
+    pending: Option<SamRec>,
+    out: Vec<u8>,
+    pos: usize,
+    n_rec: usize,
+}
+
+impl<R: Read> SamReader<R> {
+    pub(super) fn new(rdr: R, bam: bool, paired: bool) -> Self {
+        Self {
+            rdr: BufReader::new(rdr),
+            bam,
+            paired,
+            header_read: false,
+            line: String::new(),
+            raw: Vec::new(),
+            rec: SamRec::default(),
+            pending: None,
+            out: Vec::with_capacity(OUT_CHUNK << 1),
+            pos: 0,
+            n_rec: 0,
+        }
+    }
+
+    /// Generate more output.  Returns false at EOF
+    fn fill_out(&mut self) -> io::Result<bool> {
+        self.out.clear();
+        self.pos = 0;
+        if !self.header_read {
+            if self.bam {
+                self.read_bam_header()?
+            }
+            self.header_read = true
+        }
+        while self.out.len() < OUT_CHUNK {
+            let more = if self.bam {
+                self.read_bam_rec()?
+            } else {
+                self.read_sam_rec()?
+            };
+            if !more {
+                if let Some(r) = self.pending.take() {
+                    return Err(invalid(format!("Missing mate for read {}", r.name_str())));
+                }
+                break;
+            }
+            self.n_rec += 1;
+            let flag = self.rec.flag;
+            if flag & (FLAG_SECONDARY | FLAG_SUPPLEMENTARY) != 0 {
+                continue;
+            }
+            if flag & FLAG_REVERSE != 0 {
+                self.rec.reverse_complement()
+            }
+            if !self.paired {
+                self.rec.write_fastq(&mut self.out);
+                continue;
+            }
+            if flag & FLAG_PAIRED == 0 {
+                return Err(invalid(format!(
+                    "Unpaired read {} (record {})",
+                    self.rec.name_str(),
+                    self.n_rec
+                )));
+            }
+            match self.pending.take() {
+                None => self.pending = Some(std::mem::take(&mut self.rec)),
+                Some(p) => {
+                    if p.name != self.rec.name {
+                        return Err(invalid(format!(
+                            "Mates not adjacent for read {} (record {})",
+                            p.name_str(),
+                            self.n_rec
+                        )));
+                    }
+                    let (f1, f2) = (p.flag, self.rec.flag);
+                    let (r1, r2) = if f1 & FLAG_READ1 != 0 && f2 & FLAG_READ2 != 0 {
+                        (&p, &self.rec)
+                    } else if f1 & FLAG_READ2 != 0 && f2 & FLAG_READ1 != 0 {
+                        (&self.rec, &p)
+                    } else {
+                        return Err(invalid(format!(
+                            "Could not identify read 1 and read 2 for read {} (record {})",
+                            p.name_str(),
+                            self.n_rec
+                        )));
+                    };
+                    r1.write_fastq(&mut self.out);
+                    r2.write_fastq(&mut self.out);
+                    // Recycle storage from pending record
+                    self.rec = p;
+                }
+            }
+        }
+        Ok(!self.out.is_empty())
+    }
+
+    /// Read next SAM record, skipping header lines.  Returns false at EOF
+    fn read_sam_rec(&mut self) -> io::Result<bool> {
+        loop {
+            self.line.clear();
+            if self.rdr.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            let l = self.line.trim_end();
+            if l.is_empty() || l.starts_with('@') {
+                continue;
+            }
+            let fields: Vec<_> = l.split('\t').collect();
+            if fields.len() < 11 {
+                return Err(invalid(format!(
+                    "Short SAM record (record {})",
+                    self.n_rec + 1
+                )));
+            }
+            let flag = fields[1].parse::<u16>().map_err(|_| {
+                invalid(format!("Invalid SAM flag (record {})", self.n_rec + 1))
+            })?;
+            if fields[10] == "*" || fields[9].len() != fields[10].len() {
+                return Err(invalid(format!(
+                    "Missing or invalid base qualities for read {}",
+                    fields[0]
+                )));
+            }
+            let rec = &mut self.rec;
+            rec.flag = flag;
+            rec.name.clear();
+            rec.name.extend_from_slice(fields[0].as_bytes());
+            rec.seq.clear();
+            rec.seq.extend_from_slice(fields[9].as_bytes());
+            rec.qual.clear();
+            rec.qual.extend_from_slice(fields[10].as_bytes());
+            return Ok(true);
+        }
+    }
+
+    /// Check magic number and skip BAM header
+    fn read_bam_header(&mut self) -> io::Result<()> {
+        let mut magic = [0u8; 4];
+        self.rdr.read_exact(&mut magic)?;
+        if &magic != b"BAM\x01" {
+            return Err(invalid("Input is not a BAM file (or is not compressed)".to_string()));
+        }
+        let l_text = read_u32(&mut self.rdr)? as u64;
+        skip(&mut self.rdr, l_text)?;
+        let n_ref = read_u32(&mut self.rdr)?;
+        for _ in 0..n_ref {
+            let l_name = read_u32(&mut self.rdr)? as u64;
+            skip(&mut self.rdr, l_name + 4)?;
+        }
+        Ok(())
+    }
+
+    /// Read next BAM record.  Returns false at EOF
+    fn read_bam_rec(&mut self) -> io::Result<bool> {
+        if self.rdr.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let block_size = read_u32(&mut self.rdr)? as usize;
+        if block_size < 32 {
+            return Err(invalid(format!("Invalid BAM record (record {})", self.n_rec + 1)));
+        }
+        let raw = &mut self.raw;
+        raw.resize(block_size, 0);
+        self.rdr.read_exact(raw)?;
+
+        let get_u16 = |i: usize| u16::from_le_bytes([raw[i], raw[i + 1]]);
+        let l_read_name = raw[8] as usize;
+        let n_cigar = get_u16(12) as usize;
+        let flag = get_u16(14);
+        let l_seq = u32::from_le_bytes([raw[16], raw[17], raw[18], raw[19]]) as usize;
+
+        let name_off = 32;
+        let seq_off = name_off + l_read_name + (n_cigar << 2);
+        let qual_off = seq_off + l_seq.div_ceil(2);
+        if qual_off + l_seq > block_size || l_read_name == 0 {
+            return Err(invalid(format!("Invalid BAM record (record {})", self.n_rec + 1)));
+        }
+
+        let rec = &mut self.rec;
+        rec.flag = flag;
+        rec.name.clear();
+        // Read name is NUL terminated
+        rec.name
+            .extend_from_slice(&raw[name_off..name_off + l_read_name - 1]);
+
+        rec.qual.clear();
+        let q = &raw[qual_off..qual_off + l_seq];
+        if l_seq > 0 && q[0] == 0xff {
+            return Err(invalid(format!(
+                "Missing base qualities for read {}",
+                rec.name_str()
+            )));
+        }
+        rec.qual.extend(q.iter().map(|x| x.saturating_add(33)));
+
+        // Sequence is packed 2 bases per byte
+        rec.seq.clear();
+        rec.seq.extend((0..l_seq).map(|i| {
+            let b = raw[seq_off + (i >> 1)];
+            let c = if i & 1 == 0 { b >> 4 } else { b & 15 };
+            BAM_BASES[c as usize]
+        }));
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for SamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() && !self.fill_out()? {
+            return Ok(0);
+        }
+        let l = buf.len().min(self.out.len() - self.pos);
+        buf[..l].copy_from_slice(&self.out[self.pos..self.pos + l]);
+        self.pos += l;
+        Ok(l)
+    }
+}
+
+impl SamRec {
+    fn name_str(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+
+    fn reverse_complement(&mut self) {
+        self.seq.reverse();
+        // BAM sequences can contain any IUPAC code
+        for c in self.seq.iter_mut() {
+            *c = iupac_complement(c.to_ascii_uppercase())
+        }
+        self.qual.reverse();
+    }
+
+    fn write_fastq(&self, out: &mut Vec<u8>) {
+        out.push(b'@');
+        out.extend_from_slice(&self.name);
+        out.push(b'\n');
+        out.extend_from_slice(&self.seq);
+        out.extend_from_slice(b"\n+\n");
+        out.extend_from_slice(&self.qual);
+        out.push(b'\n');
+    }
+}
+
+fn invalid(s: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, s)
+}
+
+fn read_u32<R: Read>(rdr: &mut R) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    rdr.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn skip<R: Read>(rdr: &mut R, n: u64) -> io::Result<()> {
+    let l = io::copy(&mut rdr.take(n), &mut io::sink())?;
+    if l < n {
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+    } else {
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
-use std::{borrow::Cow, fmt, ops::{Deref, DerefMut}};
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use crossbeam_channel::Sender;
 
+use crate::cli::Config;
+
 const VIEW_N_REC: usize = 1024;
 
 #[repr(transparent)]
@@ -67,10 +74,12 @@ pub struct ViewBuf {
     inner: Box<[u8]>,
     rec_len: usize,
     n_rec: usize,
+    sample: usize,
+    amplicon: usize,
 }
 
 impl ViewBuf {
-    pub fn new(rec_len: usize) -> Self {
+    pub fn new(sample: usize, amplicon: usize, rec_len: usize) -> Self {
         assert!(rec_len > 0, "Record lengths cannot be zero");
         let l = VIEW_N_REC.checked_mul(rec_len).expect("rec_len too large");
         let inner = vec![0; l].into_boxed_slice();
@@ -78,9 +87,19 @@ impl ViewBuf {
             inner,
             rec_len,
             n_rec: 0,
+            sample,
+            amplicon,
         }
     }
 
+    pub fn sample(&self) -> usize {
+        self.sample
+    }
+
+    pub fn amplicon(&self) -> usize {
+        self.amplicon
+    }
+
     fn is_full(&self) -> bool {
         self.n_rec * self.rec_len >= self.inner.len()
     }
@@ -97,48 +116,73 @@ impl ViewBuf {
     }
     
     pub fn recs(&self) -> impl Iterator<Item = &ViewRec> {
-        self.inner.chunks_exact(self.rec_len).map(ViewRec::from_ptr)
+        self.inner
+            .chunks_exact(self.rec_len)
+            .take(self.n_rec)
+            .map(ViewRec::from_ptr)
     }
     
 }
 
+/// Messages sent to the view writer thread
+pub enum ViewMsg {
+    Recs(ViewBuf),
+    /// All records for the sample have been sent
+    SampleDone(usize),
+}
+
+/// View buffers for each sample and amplicon
 pub struct ViewStore {
-    inner: Option<ViewBuf>,
-    snd: Sender<ViewBuf>,
-    rec_len: usize,
+    inner: Vec<Option<ViewBuf>>,
+    snd: Sender<ViewMsg>,
+    rec_len: Vec<usize>,
 }
 
 impl ViewStore {
-    pub fn new(rec_len: usize, snd: Sender<ViewBuf>) -> Self {
+    pub fn new(n_samples: usize, rec_len: Vec<usize>, snd: Sender<ViewMsg>) -> Self {
         Self {
-            inner: None,
+            inner: (0..n_samples * rec_len.len()).map(|_| None).collect(),
             snd,
             rec_len,
         }
     }
 
-    pub fn next_view(&mut self) -> &mut ViewRec {
-        self.check_or_send();
-        self.inner.as_mut().and_then(|v| v.next_mut()).unwrap()
+    pub fn next_view(&mut self, sample: usize, amplicon: usize) -> &mut ViewRec {
+        let k = self.check_or_send(sample, amplicon);
+        self.inner[k].as_mut().and_then(|v| v.next_mut()).unwrap()
     }
 
     pub fn flush(&mut self) {
-        if let Some(v) = self.inner.take()
-            && v.n_rec > 0
-        {
-            self.snd.send(v).expect("Error sending view for printing")
+        for v in self.inner.iter_mut().filter_map(|v| v.take()) {
+            if v.n_rec > 0 {
+                self.snd.send(ViewMsg::Recs(v)).expect("Error sending view for printing")
+            }
+        }
+    }
+
+    /// Send the buffers for `sample`, and mark the `n` input buffers for the sample that have
+    /// been processed as finished
+    pub fn finish_sample(&mut self, sample: usize, n: usize, progress: &SampleProgress) {
+        let k = self.rec_len.len();
+        for v in self.inner[sample * k..(sample + 1) * k].iter_mut().filter_map(|v| v.take()) {
+            if v.n_rec > 0 {
+                self.snd.send(ViewMsg::Recs(v)).expect("Error sending view for printing")
+            }
         }
+        progress.done(sample, n, &self.snd)
     }
 
-    fn check_or_send(&mut self) {
-        if let Some(v) = self.inner.as_mut() {
+    fn check_or_send(&mut self, sample: usize, amplicon: usize) -> usize {
+        let k = sample * self.rec_len.len() + amplicon;
+        if let Some(v) = self.inner[k].as_mut() {
             if !v.is_full() {
-                return;
+                return k;
             }
-            let v = self.inner.take().unwrap();
-            self.snd.send(v).expect("Error sending view for printing")
+            let v = self.inner[k].take().unwrap();
+            self.snd.send(ViewMsg::Recs(v)).expect("Error sending view for printing")
         }
-        self.inner = Some(ViewBuf::new(self.rec_len));
+        self.inner[k] = Some(ViewBuf::new(sample, amplicon, self.rec_len[amplicon]));
+        k
     }
 }
 
@@ -146,4 +190,34 @@ impl Drop for ViewStore {
     fn drop(&mut self) {
         self.flush()
     }
-}
\ No newline at end of file
+}
+
+/// Number of inputs still being read and buffers still being processed for each sample, so
+/// that the view writer can close the output files for a sample once it has been completed
+pub struct SampleProgress {
+    pending: Vec<AtomicUsize>,
+}
+
+impl SampleProgress {
+    pub fn new(cfg: &Config) -> Self {
+        let pending: Vec<_> = (0..cfg.n_samples()).map(|_| AtomicUsize::new(0)).collect();
+        for i in cfg.inputs() {
+            pending[i.sample()].fetch_add(1, Ordering::Relaxed);
+        }
+        Self { pending }
+    }
+
+    /// Add a buffer for `sample`.  This must be called before the buffer is sent for processing
+    pub fn add_buffer(&self, sample: usize) {
+        self.pending[sample].fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Mark `n` inputs or buffers for `sample` as finished.  If nothing is left pending for the
+    /// sample, the view writer is told that the sample is complete
+    pub fn done(&self, sample: usize, n: usize, snd: &Sender<ViewMsg>) {
+        if n > 0 && self.pending[sample].fetch_sub(n, Ordering::AcqRel) == n {
+            snd.send(ViewMsg::SampleDone(sample))
+                .expect("Error sending view for printing")
+        }
+    }
+}
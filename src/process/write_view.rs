@@ -1,22 +1,44 @@
-use std::io::Write;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    io::Write,
+};
 
 use crossbeam_channel::Receiver;
 use compress_io::compress::CompressIo;
 
 use crate::cli::Config;
 
-use super::view::ViewBuf;
+use super::view::ViewMsg;
 
-pub fn write_view(cfg: &Config, rcv: Receiver<ViewBuf>) -> anyhow::Result<()> {
+pub fn write_view(cfg: &Config, rcv: Receiver<ViewMsg>) -> anyhow::Result<()> {
     
     debug!("Starting up view writer thread");
     
-    let out_name = format!("{}_view.txt.gz", cfg.output_prefix());
-    let mut wrt = CompressIo::new().path(&out_name).bufwriter()?;
+    // Output files are opened on demand, one per sample and amplicon, and closed when
+    // the sample is complete
+    let mut writers = HashMap::new();
     
-    while let Ok(vb) = rcv.recv() {
-        for r in vb.recs() {
-            writeln!(wrt, "{}", r)?
+    while let Ok(msg) = rcv.recv() {
+        match msg {
+            ViewMsg::Recs(vb) => {
+                let (sample, ix) = (vb.sample(), vb.amplicon());
+                let wrt = match writers.entry((sample, ix)) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => {
+                        let out_name = format!("{}_view.txt.gz", cfg.amplicon_prefix(sample, ix));
+                        e.insert(CompressIo::new().path(&out_name).bufwriter()?)
+                    }
+                };
+                for r in vb.recs() {
+                    writeln!(wrt, "{}", r)?
+                }
+            }
+            ViewMsg::SampleDone(sample) => {
+                debug!("Closing view files for sample {sample}");
+                for (_, mut wrt) in writers.extract_if(|(s, _), _| *s == sample) {
+                    wrt.flush()?
+                }
+            }
         }
     }
         